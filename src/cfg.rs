@@ -0,0 +1,570 @@
+//! Analyses of the MIR block graph of a pass.
+//!
+//! Edges come from the `successors` of each block, and the entry is the first
+//! block. Malformed blocks, and successors that point at no block, are left
+//! out.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::MirBlock;
+
+/// The block graph of a pass, with the blocks numbered by their position
+struct Graph<'a> {
+    blocks: Vec<&'a MirBlock>,
+    succs: Vec<Vec<usize>>,
+    preds: Vec<Vec<usize>>,
+}
+
+impl<'a> Graph<'a> {
+
+    fn new(blocks: &'a [MirBlock]) -> Graph<'a> {
+
+        let blocks = blocks.iter()
+                           .filter(|b| b.malformed.is_none())
+                           .collect::<Vec<_>>();
+        let index = blocks.iter()
+                          .enumerate()
+                          .map(|(idx, block)| (block.number, idx))
+                          .collect::<HashMap<_, _>>();
+
+        let mut succs = vec![Vec::new(); blocks.len()];
+        let mut preds = vec![Vec::new(); blocks.len()];
+        for (from, block) in blocks.iter().enumerate() {
+            for to in block.successors.iter().filter_map(|s| index.get(s)) {
+                succs[from].push(*to);
+                preds[*to].push(from);
+            }
+        }
+
+        Graph { blocks, succs, preds }
+    }
+
+    /// Blocks reachable from the entry, in reverse postorder
+    fn reverse_postorder(&self) -> Vec<usize> {
+
+        let mut order = Vec::new();
+        if self.blocks.is_empty() {
+            return order;
+        }
+
+        let mut seen  = vec![false; self.blocks.len()];
+        let mut stack = vec![(0, 0)];
+        seen[0] = true;
+
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            match self.succs[node].get(*next) {
+                Some(&succ) => {
+                    *next += 1;
+                    if !seen[succ] {
+                        seen[succ] = true;
+                        stack.push((succ, 0));
+                    }
+                }
+                None => {
+                    order.push(node);
+                    stack.pop();
+                }
+            }
+        }
+
+        order.reverse();
+        order
+    }
+
+    /// Immediate dominator of every reachable block, the entry being its own.
+    /// This is the iterative algorithm of Cooper, Harvey and Kennedy.
+    fn idoms(&self) -> Vec<Option<usize>> {
+
+        let order = self.reverse_postorder();
+
+        let mut rank = vec![usize::MAX; self.blocks.len()];
+        for (idx, &node) in order.iter().enumerate() {
+            rank[node] = idx;
+        }
+
+        let mut idom = vec![None; self.blocks.len()];
+        if let Some(&entry) = order.first() {
+            idom[entry] = Some(entry);
+        }
+
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while rank[a] > rank[b] {
+                    a = idom[a].unwrap();
+                }
+                while rank[b] > rank[a] {
+                    b = idom[b].unwrap();
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for &node in order.iter().skip(1) {
+                let mut new = None;
+                for &pred in self.preds[node].iter().filter(|&&p| idom[p].is_some()) {
+                    new = Some(match new {
+                        None => pred,
+                        Some(new) => intersect(&idom, pred, new),
+                    });
+                }
+
+                if new.is_some() && idom[node] != new {
+                    idom[node] = new;
+                    changed = true;
+                }
+            }
+        }
+
+        idom
+    }
+}
+
+/// Immediate dominator of every block reachable from the entry, by block
+/// number. The entry block is left out, as it has none.
+pub fn immediate_dominators(blocks: &[MirBlock]) -> HashMap<u32, u32> {
+
+    let graph = Graph::new(blocks);
+
+    graph.idoms()
+         .iter()
+         .enumerate()
+         .filter_map(|(node, idom)| match idom {
+             Some(idom) if *idom != node => Some((graph.blocks[node].number,
+                                                  graph.blocks[*idom].number)),
+             _ => None,
+         })
+         .collect()
+}
+
+/// The dominator tree, each block indented under its immediate dominator.
+/// Blocks that cannot be reached from the entry are listed after it.
+pub fn parse_dominator_tree(debugout: &mut impl Write, blocks: &[MirBlock]) -> io::Result<()> {
+
+    let graph = Graph::new(blocks);
+    if graph.blocks.is_empty() {
+        return Ok(());
+    }
+
+    let idoms = graph.idoms();
+
+    let mut children = vec![Vec::new(); graph.blocks.len()];
+    for (node, idom) in idoms.iter().enumerate() {
+        match idom {
+            Some(idom) if *idom != node => children[*idom].push(node),
+            _ => {}
+        }
+    }
+
+    writeln!(debugout, "\n      Dominator tree")?;
+
+    let mut stack = vec![(0, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        writeln!(debugout, "        {:indent$}Block#{}", "", graph.blocks[node].number,
+                 indent = depth * 2)?;
+
+        for &child in children[node].iter().rev() {
+            stack.push((child, depth + 1));
+        }
+    }
+
+    for (node, _) in idoms.iter().enumerate().filter(|(_, idom)| idom.is_none()) {
+        writeln!(debugout, "        Block#{} (unreachable)", graph.blocks[node].number)?;
+    }
+
+    Ok(())
+}
+
+/// A natural loop. Blocks are listed by number, in the order of the pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: u32,
+
+    /// Every block of the loop, the header and the nested loops included
+    pub body: Vec<u32>,
+
+    /// Blocks jumping back to the header
+    pub backedges: Vec<u32>,
+
+    /// Blocks outside of the loop that the body jumps to
+    pub exits: Vec<u32>,
+
+    /// 1 for an outermost loop
+    pub depth: u32,
+
+    /// Index of the innermost loop this one is nested in
+    pub parent: Option<usize>,
+}
+
+/// Where the loops we found disagree with what Ion recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Marked `loopheader`, but no backedge goes to it
+    NotAHeader(u32),
+
+    /// Heads a loop, but is not marked `loopheader`
+    UnmarkedHeader(u32),
+
+    /// Marked `backedge`, but does not jump back to a header
+    NotABackedge(u32),
+
+    /// Jumps back to a header, but is not marked `backedge`
+    UnmarkedBackedge(u32),
+
+    /// Block, `loopDepth` in the JSON and the depth we found
+    Depth(u32, u32, u32),
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::NotAHeader(block) => {
+                write!(f, "Block#{} is marked loopheader, but no backedge goes to it", block)
+            }
+            Mismatch::UnmarkedHeader(block) => {
+                write!(f, "Block#{} heads a loop, but is not marked loopheader", block)
+            }
+            Mismatch::NotABackedge(block) => {
+                write!(f, "Block#{} is marked backedge, but does not jump back to a loop header", block)
+            }
+            Mismatch::UnmarkedBackedge(block) => {
+                write!(f, "Block#{} jumps back to a loop header, but is not marked backedge", block)
+            }
+            Mismatch::Depth(block, recorded, found) => {
+                write!(f, "Block#{} has loopDepth {}, but is in {} loop{}", block, recorded, found,
+                       if *found == 1 { "" } else { "s" })
+            }
+        }
+    }
+}
+
+impl<'a> Graph<'a> {
+
+    fn dominates(&self, idoms: &[Option<usize>], a: usize, mut b: usize) -> bool {
+
+        loop {
+            if a == b {
+                return true;
+            }
+            match idoms[b] {
+                Some(idom) if idom != b => b = idom,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Body of every natural loop, with its header and backedges. Backedges
+    /// to the same header make a single loop.
+    fn loops(&self, idoms: &[Option<usize>]) -> Vec<(usize, Vec<usize>, Vec<bool>)> {
+
+        let mut loops: Vec<(usize, Vec<usize>, Vec<bool>)> = Vec::new();
+
+        for (from, succs) in self.succs.iter().enumerate().filter(|(from, _)| idoms[*from].is_some()) {
+            for &header in succs.iter().filter(|&&to| self.dominates(idoms, to, from)) {
+                let idx = match loops.iter().position(|l| l.0 == header) {
+                    Some(idx) => idx,
+                    None => {
+                        let mut body = vec![false; self.blocks.len()];
+                        body[header] = true;
+                        loops.push((header, Vec::new(), body));
+                        loops.len() - 1
+                    }
+                };
+
+                let (_, backedges, body) = &mut loops[idx];
+                backedges.push(from);
+
+                // Blocks that cannot be reached from the entry are in no
+                // loop, even when they jump into one
+                let mut todo = vec![from];
+                while let Some(node) = todo.pop() {
+                    if !body[node] {
+                        body[node] = true;
+                        todo.extend(self.preds[node].iter().filter(|&&p| idoms[p].is_some()));
+                    }
+                }
+            }
+        }
+
+        loops.sort_by_key(|l| l.0);
+        loops
+    }
+}
+
+/// Natural loops of the block graph, outer loops before the loops nested in
+/// them
+pub fn natural_loops(blocks: &[MirBlock]) -> Vec<Loop> {
+
+    let graph = Graph::new(blocks);
+    let idoms = graph.idoms();
+    let found = graph.loops(&idoms);
+
+    let numbers = |nodes: &mut dyn Iterator<Item = usize>| {
+        nodes.map(|node| graph.blocks[node].number).collect::<Vec<_>>()
+    };
+
+    found.iter().enumerate().map(|(idx, (header, backedges, body))| {
+        let mut exits = (0..graph.blocks.len())
+            .filter(|&node| !body[node])
+            .filter(|&node| graph.preds[node].iter().any(|&p| body[p]))
+            .collect::<Vec<_>>();
+        exits.sort();
+
+        // The loops around this one are the ones whose body has its header,
+        // the innermost being the smallest
+        let outer = found.iter()
+                         .enumerate()
+                         .filter(|(other, l)| *other != idx && l.2[*header])
+                         .collect::<Vec<_>>();
+        let parent = outer.iter()
+                          .min_by_key(|(_, l)| l.2.iter().filter(|&&b| b).count())
+                          .map(|(other, _)| *other);
+
+        Loop {
+            header: graph.blocks[*header].number,
+            body: numbers(&mut (0..graph.blocks.len()).filter(|&node| body[node])),
+            backedges: numbers(&mut backedges.iter().copied()),
+            exits: numbers(&mut exits.into_iter()),
+            depth: outer.len() as u32 + 1,
+            parent,
+        }
+    }).collect()
+}
+
+/// Compare `loops` with the `loopheader` and `backedge` attributes and the
+/// `loopDepth` of the blocks. Blocks that cannot be reached from the entry
+/// are not checked.
+pub fn check_loops(blocks: &[MirBlock], loops: &[Loop]) -> Vec<Mismatch> {
+
+    let graph = Graph::new(blocks);
+    let idoms = graph.idoms();
+
+    let mut mismatches = Vec::new();
+
+    for (node, block) in graph.blocks.iter().enumerate().filter(|(node, _)| idoms[*node].is_some()) {
+        let header   = loops.iter().any(|l| l.header == block.number);
+        let backedge = loops.iter().any(|l| l.backedges.contains(&block.number));
+
+        match (block.has_attribute("loopheader"), header) {
+            (true, false) => mismatches.push(Mismatch::NotAHeader(block.number)),
+            (false, true) => mismatches.push(Mismatch::UnmarkedHeader(block.number)),
+            _ => {}
+        }
+
+        match (block.has_attribute("backedge"), backedge) {
+            (true, false) => mismatches.push(Mismatch::NotABackedge(block.number)),
+            (false, true) => mismatches.push(Mismatch::UnmarkedBackedge(block.number)),
+            _ => {}
+        }
+
+        let depth = loops.iter().filter(|l| l.body.contains(&graph.blocks[node].number)).count() as u32;
+        if depth != block.loop_depth {
+            mismatches.push(Mismatch::Depth(block.number, block.loop_depth, depth));
+        }
+    }
+
+    mismatches
+}
+
+fn join(numbers: &[u32]) -> String {
+    numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Every loop with its body, backedges and exits, nested loops indented
+/// under the loop around them, then where Ion disagrees with us
+pub fn parse_loops(debugout: &mut impl Write, blocks: &[MirBlock]) -> io::Result<()> {
+
+    let loops = natural_loops(blocks);
+
+    writeln!(debugout, "\n      Loops")?;
+    if loops.is_empty() {
+        writeln!(debugout, "        none")?;
+    }
+
+    let mut stack = loops.iter()
+                         .enumerate()
+                         .rev()
+                         .filter(|(_, l)| l.parent.is_none())
+                         .map(|(idx, _)| idx)
+                         .collect::<Vec<_>>();
+
+    while let Some(idx) = stack.pop() {
+        let l = &loops[idx];
+        let indent = (l.depth as usize - 1) * 2;
+
+        writeln!(debugout, "        {:indent$}Loop at Block#{}, depth {}", "", l.header, l.depth, indent = indent)?;
+        writeln!(debugout, "        {:indent$}  body: {}", "", join(&l.body), indent = indent)?;
+        writeln!(debugout, "        {:indent$}  backedges: {}", "", join(&l.backedges), indent = indent)?;
+        writeln!(debugout, "        {:indent$}  exits: {}", "", join(&l.exits), indent = indent)?;
+
+        stack.extend(loops.iter()
+                          .enumerate()
+                          .rev()
+                          .filter(|(_, inner)| inner.parent == Some(idx))
+                          .map(|(inner, _)| inner));
+    }
+
+    let mismatches = check_loops(blocks, &loops);
+    if !mismatches.is_empty() {
+        writeln!(debugout, "\n      Loops disagree with Ion")?;
+        for mismatch in mismatches {
+            writeln!(debugout, "        {}", mismatch)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Blocks numbered as given, each with its successors
+    fn blocks(successors: &[&[u32]]) -> Vec<MirBlock> {
+        successors.iter()
+                  .enumerate()
+                  .map(|(number, succs)| MirBlock {
+                      number: number as u32,
+                      successors: succs.to_vec(),
+                      ..Default::default()
+                  })
+                  .collect()
+    }
+
+    fn idoms(pairs: &[(u32, u32)]) -> HashMap<u32, u32> {
+        pairs.iter().copied().collect()
+    }
+
+    /// 0 -> 1 -> 2 -> 3 -> 4 -> 5, with 3 -> 2 and 4 -> 1 going back
+    fn nested() -> Vec<MirBlock> {
+        blocks(&[&[1], &[2], &[3], &[2, 4], &[1, 5], &[]])
+    }
+
+    #[test]
+    fn diamond_dominators() {
+        let diamond = blocks(&[&[1, 2], &[3], &[3], &[]]);
+        assert_eq!(immediate_dominators(&diamond), idoms(&[(1, 0), (2, 0), (3, 0)]));
+    }
+
+    #[test]
+    fn nested_loop_dominators() {
+        assert_eq!(immediate_dominators(&nested()),
+                   idoms(&[(1, 0), (2, 1), (3, 2), (4, 3), (5, 4)]));
+    }
+
+    #[test]
+    fn self_loop_dominators() {
+        let self_loop = blocks(&[&[1], &[1, 2], &[]]);
+        assert_eq!(immediate_dominators(&self_loop), idoms(&[(1, 0), (2, 1)]));
+    }
+
+    #[test]
+    fn unreachable_predecessor_dominators() {
+        // Block 3 jumps into 2, but nothing jumps to 3
+        let unreachable = blocks(&[&[1], &[2], &[], &[2]]);
+        assert_eq!(immediate_dominators(&unreachable), idoms(&[(1, 0), (2, 1)]));
+    }
+
+    #[test]
+    fn join_of_two_paths() {
+        // 1 and 2 reach each other, so only the entry dominates them
+        let join = blocks(&[&[1, 2], &[2], &[1]]);
+        assert_eq!(immediate_dominators(&join), idoms(&[(1, 0), (2, 0)]));
+    }
+
+    #[test]
+    fn dominator_tree() {
+        let mut debugout = Vec::new();
+        parse_dominator_tree(&mut debugout, &blocks(&[&[1, 2], &[3], &[3], &[], &[3]])).unwrap();
+
+        assert_eq!(String::from_utf8(debugout).unwrap(), concat!(
+            "\n      Dominator tree\n",
+            "        Block#0\n",
+            "          Block#1\n",
+            "          Block#2\n",
+            "          Block#3\n",
+            "        Block#4 (unreachable)\n"));
+    }
+
+    /// Mark the blocks the way Ion would for `nested`
+    fn marked(mut blocks: Vec<MirBlock>) -> Vec<MirBlock> {
+        for (number, attribute, depth) in [(1, Some("loopheader"), 1), (2, Some("loopheader"), 2),
+                                           (3, Some("backedge"), 2), (4, Some("backedge"), 1),
+                                           (0, None, 0), (5, None, 0)] {
+            let block = &mut blocks[number];
+            block.attributes.extend(attribute.map(String::from));
+            block.loop_depth = depth;
+        }
+        blocks
+    }
+
+    #[test]
+    fn diamond_has_no_loop() {
+        let diamond = blocks(&[&[1, 2], &[3], &[3], &[]]);
+        assert!(natural_loops(&diamond).is_empty());
+        assert!(check_loops(&diamond, &[]).is_empty());
+    }
+
+    #[test]
+    fn nested_loops() {
+        assert_eq!(natural_loops(&nested()), vec![
+            Loop { header: 1, body: vec![1, 2, 3, 4], backedges: vec![4], exits: vec![5],
+                   depth: 1, parent: None },
+            Loop { header: 2, body: vec![2, 3], backedges: vec![3], exits: vec![4],
+                   depth: 2, parent: Some(0) },
+        ]);
+    }
+
+    #[test]
+    fn self_loop() {
+        let self_loop = blocks(&[&[1], &[1, 2], &[]]);
+        assert_eq!(natural_loops(&self_loop), vec![
+            Loop { header: 1, body: vec![1], backedges: vec![1], exits: vec![2],
+                   depth: 1, parent: None },
+        ]);
+    }
+
+    #[test]
+    fn unreachable_predecessor_is_not_in_the_loop() {
+        // Block 4 jumps into the loop, but nothing jumps to 4
+        let unreachable = blocks(&[&[1], &[2], &[1, 3], &[], &[2]]);
+        assert_eq!(natural_loops(&unreachable), vec![
+            Loop { header: 1, body: vec![1, 2], backedges: vec![2], exits: vec![3],
+                   depth: 1, parent: None },
+        ]);
+    }
+
+    #[test]
+    fn loops_agree_with_ion() {
+        let blocks = marked(nested());
+        assert!(check_loops(&blocks, &natural_loops(&blocks)).is_empty());
+    }
+
+    #[test]
+    fn loops_disagree_with_ion() {
+        let mut blocks = marked(nested());
+        blocks[2].attributes.clear();
+        blocks[3].loop_depth = 1;
+        blocks[5].attributes.push("backedge".to_string());
+
+        assert_eq!(check_loops(&blocks, &natural_loops(&blocks)), vec![
+            Mismatch::UnmarkedHeader(2),
+            Mismatch::Depth(3, 1, 2),
+            Mismatch::NotABackedge(5),
+        ]);
+    }
+
+    #[test]
+    fn unreachable_blocks_are_not_checked() {
+        let mut blocks = blocks(&[&[], &[1, 0]]);
+        blocks[1].attributes.push("loopheader".to_string());
+        blocks[1].loop_depth = 3;
+
+        assert!(check_loops(&blocks, &natural_loops(&blocks)).is_empty());
+    }
+}
@@ -0,0 +1,515 @@
+//! What each optimization pass changed, as a unified-diff-like listing of
+//! the MIR instructions that were added, removed or changed between two
+//! consecutive passes.
+//!
+//! Instructions are matched up by their `id`, which MIR keeps stable across
+//! passes. An instruction that changed opcode, operands or type, or moved to
+//! another block, shows up as removed from its old block and added to its new
+//! one.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::{self, Write};
+
+use crate::{Function, IonLog, MirInstruction, Pass};
+
+/// The changed lines of one block, in the order of the instructions
+#[derive(Debug, Clone)]
+pub struct BlockDiff<'a> {
+    pub number: u32,
+
+    /// `-` for lines only in the old pass, `+` for lines only in the new one
+    pub lines: Vec<(char, &'a MirInstruction)>,
+}
+
+/// Every instruction of a pass with the number of the block it is in
+fn instructions(pass: &Pass) -> HashMap<u32, (u32, &MirInstruction)> {
+    pass.mir.blocks.iter()
+                   .filter(|b| b.malformed.is_none())
+                   .flat_map(|b| b.instructions.iter().map(move |i| (b.number, i)))
+                   .filter(|(_, i)| i.malformed.is_none())
+                   .map(|(block, i)| (i.id, (block, i)))
+                   .collect()
+}
+
+/// Diff the MIR of two passes, keyed by instruction id. Blocks without any
+/// change are left out.
+pub fn diff_passes<'a>(old: &'a Pass, new: &'a Pass) -> Vec<BlockDiff<'a>> {
+
+    let old_instrs = instructions(old);
+    let new_instrs = instructions(new);
+
+    // Whether the instruction is the same on the other side
+    let unchanged = |block: u32, instr: &MirInstruction, other: &HashMap<u32, (u32, &MirInstruction)>| {
+        match other.get(&instr.id) {
+            Some((other_block, other_instr)) => *other_block == block &&
+                                                other_instr.opcode == instr.opcode &&
+                                                other_instr.ty == instr.ty,
+            None => false,
+        }
+    };
+
+    let numbers = old.mir.blocks.iter()
+                                .chain(new.mir.blocks.iter())
+                                .filter(|b| b.malformed.is_none())
+                                .map(|b| b.number)
+                                .collect::<BTreeSet<_>>();
+
+    let mut diffs = Vec::new();
+
+    for number in numbers {
+        let mut lines = Vec::new();
+
+        for (sign, pass, other) in [('-', old, &new_instrs), ('+', new, &old_instrs)] {
+            let block = pass.mir.blocks.iter()
+                                       .find(|b| b.malformed.is_none() && b.number == number);
+
+            for instr in block.iter().flat_map(|b| b.instructions.iter()) {
+                if instr.malformed.is_none() && !unchanged(number, instr, other) {
+                    lines.push((sign, instr));
+                }
+            }
+        }
+
+        if !lines.is_empty() {
+            diffs.push(BlockDiff { number, lines });
+        }
+    }
+
+    diffs
+}
+
+/// Render the block diffs, aligning the columns the same way the text dump
+/// does
+pub fn parse_block_diffs(debugout: &mut impl Write, diffs: &[BlockDiff]) -> io::Result<()> {
+
+    for diff in diffs.iter() {
+        let mut opcode_len  = 0;
+        let mut operand_len = 0;
+        for (_, instr) in diff.lines.iter() {
+            let (opcode, operand) = instr.opcode_and_operand();
+            opcode_len  = opcode_len.max(opcode.len());
+            operand_len = operand_len.max(operand.len());
+        }
+
+        writeln!(debugout, "@@ Block#{} @@", diff.number)?;
+
+        for (sign, instr) in diff.lines.iter() {
+            let (opcode, operand) = instr.opcode_and_operand();
+
+            writeln!(debugout, "{} {:>4}: {:<opw$} {:<orw$} {:?}",
+                                 sign, instr.id, opcode, operand, instr.ty,
+                                 opw = opcode_len + 5, orw = operand_len + 5)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Diff every pass of a function against the pass before it
+pub fn parse_function(debugout: &mut impl Write, func: &Function) -> io::Result<()> {
+
+    if func.malformed.is_some() {
+        return Ok(());
+    }
+
+    writeln!(debugout, "\n\nGraph for Function: {:?}", func.name)?;
+
+    let passes = func.passes.iter()
+                            .filter(|p| p.malformed.is_none())
+                            .collect::<Vec<_>>();
+
+    for pair in passes.windows(2) {
+        let (old, new) = (pair[0], pair[1]);
+
+        writeln!(debugout, "\n--- {:?}\n+++ {:?}", old.name, new.name)?;
+
+        let diffs = diff_passes(old, new);
+        if diffs.is_empty() {
+            writeln!(debugout, "    (no changes)")?;
+        }
+
+        parse_block_diffs(debugout, &diffs)?;
+    }
+
+    Ok(())
+}
+
+/// Diff every pass of every function against the pass before it
+pub fn parse_graph(debugout: &mut impl Write, iondata: &IonLog) -> io::Result<()> {
+
+    for func in iondata.functions.iter() {
+        parse_function(debugout, func)?;
+    }
+
+    Ok(())
+}
+
+/// The opcode and operands of an instruction with the ids dropped from the
+/// operands that name other instructions, eg. `add constant parameter` for
+/// `add constant3 parameter1`
+fn without_ids(instr: &MirInstruction) -> String {
+
+    let mut words = instr.opcode.split_whitespace();
+    let mut key   = words.next().unwrap_or_default().to_string();
+
+    for word in words {
+        let name = word.trim_end_matches(|c: char| c.is_ascii_digit());
+        let reference = name.len() < word.len() &&
+                        !name.is_empty() &&
+                        name.chars().all(|c| c.is_ascii_alphabetic());
+
+        key.push(' ');
+        key.push_str(if reference { name } else { word });
+    }
+
+    key
+}
+
+/// Beyond this many differences between two blocks, the rest of them is
+/// listed as removed and added again rather than diffed any further
+const MAX_EDITS: usize = 4096;
+
+/// Middle snake of Myers' diff of `old` and `new`, as the start and end of a
+/// run of equal entries halfway along a shortest edit script. None when the
+/// two lists have no common entry, or more than [`MAX_EDITS`] differences.
+fn middle_snake(old: &[u32], new: &[u32]) -> Option<((usize, usize), (usize, usize))> {
+
+    let (n, m)  = (old.len() as isize, new.len() as isize);
+    let delta   = n - m;
+    let odd     = delta % 2 != 0;
+    let max     = ((n + m + 1) / 2).min(MAX_EDITS as isize);
+    let offset  = max + 1;
+
+    // Furthest x reached on every diagonal k = x - y, going forwards from the
+    // start and backwards from the end. The backward ones are counted from the
+    // end too, and their diagonal k is delta - k going forwards.
+    let mut forward  = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)]
+            } else {
+                forward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let start = (x as usize, y as usize);
+
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+
+            let back = delta - k;
+            if odd && -d < back && back < d && x + backward[at(back)] >= n {
+                return Some((start, (x as usize, y as usize)));
+            }
+        }
+
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                backward[at(k + 1)]
+            } else {
+                backward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let end = ((n - x) as usize, (m - y) as usize);
+
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(k)] = x;
+
+            let front = delta - k;
+            if !odd && -d <= front && front <= d && forward[at(front)] + x >= n {
+                return Some((((n - x) as usize, (m - y) as usize), end));
+            }
+        }
+    }
+
+    None
+}
+
+/// Mark the entries of `old` and `new` that are part of their longest common
+/// subsequence, splitting the lists around the middle snake so that this
+/// takes linear space
+fn common(old: &[u32], new: &[u32], old_kept: &mut [bool], new_kept: &mut [bool]) {
+
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+                              .zip(new[prefix..].iter().rev())
+                              .take_while(|(a, b)| a == b)
+                              .count();
+
+    old_kept[..prefix].fill(true);
+    new_kept[..prefix].fill(true);
+    old_kept[old.len() - suffix..].fill(true);
+    new_kept[new.len() - suffix..].fill(true);
+
+    let old      = &old[prefix..old.len() - suffix];
+    let new      = &new[prefix..new.len() - suffix];
+    let old_kept = &mut old_kept[prefix..prefix + old.len()];
+    let new_kept = &mut new_kept[prefix..prefix + new.len()];
+
+    if old.is_empty() || new.is_empty() {
+        return;
+    }
+
+    if let Some(((x0, y0), (x1, y1))) = middle_snake(old, new) {
+        old_kept[x0..x1].fill(true);
+        new_kept[y0..y1].fill(true);
+
+        let (old_head, old_tail) = old_kept.split_at_mut(x1);
+        let (new_head, new_tail) = new_kept.split_at_mut(y1);
+        common(&old[..x0], &new[..y0], &mut old_head[..x0], &mut new_head[..y0]);
+        common(&old[x1..], &new[y1..], old_tail, new_tail);
+    }
+}
+
+/// Line based diff of two lists of instructions, ignoring their ids
+fn diff_instructions<'a>(old: &[&'a MirInstruction], new: &[&'a MirInstruction])
+    -> Vec<(char, &'a MirInstruction)> {
+
+    // Number the distinct instructions so that the diff compares integers
+    let mut numbers = HashMap::new();
+    let mut number  = |instr: &MirInstruction| {
+        let next = numbers.len() as u32;
+        *numbers.entry((without_ids(instr), instr.ty.clone())).or_insert(next)
+    };
+    let old_keys = old.iter().map(|i| number(i)).collect::<Vec<_>>();
+    let new_keys = new.iter().map(|i| number(i)).collect::<Vec<_>>();
+
+    let mut old_kept = vec![false; old.len()];
+    let mut new_kept = vec![false; new.len()];
+    common(&old_keys, &new_keys, &mut old_kept, &mut new_kept);
+
+    // The kept entries pair up in order, whatever is in between them changed
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && !old_kept[i] {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else if j < new.len() && !new_kept[j] {
+            lines.push(('+', new[j]));
+            j += 1;
+        } else {
+            i += 1;
+            j += 1;
+        }
+    }
+
+    lines
+}
+
+/// Diff the MIR of the same pass from two different compilations, block by
+/// block. Unlike [`diff_passes`] this does not rely on the instruction ids,
+/// since those shift as soon as one compilation creates an extra instruction:
+/// neither the ids nor the ids in the operands are compared.
+pub fn diff_compilations<'a>(old: &'a Pass, new: &'a Pass) -> Vec<BlockDiff<'a>> {
+
+    let block = |pass: &'a Pass, number: u32| {
+        pass.mir.blocks.iter()
+                       .filter(|b| b.malformed.is_none() && b.number == number)
+                       .flat_map(|b| b.instructions.iter())
+                       .filter(|i| i.malformed.is_none())
+                       .collect::<Vec<_>>()
+    };
+
+    let numbers = old.mir.blocks.iter()
+                                .chain(new.mir.blocks.iter())
+                                .filter(|b| b.malformed.is_none())
+                                .map(|b| b.number)
+                                .collect::<BTreeSet<_>>();
+
+    numbers.into_iter()
+           .map(|number| BlockDiff {
+               number,
+               lines: diff_instructions(&block(old, number), &block(new, number)),
+           })
+           .filter(|diff| !diff.lines.is_empty())
+           .collect()
+}
+
+/// Pair up entries of two lists by name. The n-th entry with a given name on
+/// one side goes with the n-th one with that name on the other, since the same
+/// script is often compiled more than once.
+fn pair_by_name<'a, T>(old: &'a [T], new: &'a [T], name: fn(&T) -> &str)
+    -> Vec<(Option<&'a T>, Option<&'a T>)> {
+
+    let mut unmatched: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (idx, entry) in new.iter().enumerate() {
+        unmatched.entry(name(entry)).or_default().push_back(idx);
+    }
+
+    let mut matched = vec![false; new.len()];
+    let mut pairs   = Vec::new();
+
+    for entry in old.iter() {
+        let other = unmatched.get_mut(name(entry)).and_then(|idxs| idxs.pop_front());
+        if let Some(idx) = other {
+            matched[idx] = true;
+        }
+
+        pairs.push((Some(entry), other.map(|idx| &new[idx])));
+    }
+
+    // Whatever is left only exists on the new side
+    for (idx, entry) in new.iter().enumerate() {
+        if !matched[idx] {
+            pairs.push((None, Some(entry)));
+        }
+    }
+
+    pairs
+}
+
+/// Compare two logs of the same script from different engine builds. Functions
+/// and passes are matched up by name, and the passes whose MIR differ are
+/// listed with their per-block differences. The first of them is where the two
+/// compilations diverge.
+pub fn compare(debugout: &mut impl Write, old: &IonLog, new: &IonLog) -> io::Result<()> {
+
+    for pair in pair_by_name(&old.functions, &new.functions, |f| &f.name) {
+        let (old_func, new_func) = match pair {
+            (Some(func), _) | (_, Some(func)) if func.malformed.is_some() => continue,
+            (Some(old_func), Some(new_func)) => (old_func, new_func),
+            (Some(old_func), None) => {
+                writeln!(debugout, "\n\nFunction {:?} is only in the old log", old_func.name)?;
+                continue;
+            }
+            (None, Some(new_func)) => {
+                writeln!(debugout, "\n\nFunction {:?} is only in the new log", new_func.name)?;
+                continue;
+            }
+            (None, None) => continue,
+        };
+
+        writeln!(debugout, "\n\nGraph for Function: {:?}", old_func.name)?;
+
+        let mut diverged  = false;
+        let mut identical = 0;
+
+        for pair in pair_by_name(&old_func.passes, &new_func.passes, |p| &p.name) {
+            let (old_pass, new_pass) = match pair {
+                (Some(pass), _) | (_, Some(pass)) if pass.malformed.is_some() => continue,
+                (Some(old_pass), Some(new_pass)) => (old_pass, new_pass),
+                (Some(old_pass), None) => {
+                    writeln!(debugout, "\n  Pass {:?} only ran in the old log", old_pass.name)?;
+                    continue;
+                }
+                (None, Some(new_pass)) => {
+                    writeln!(debugout, "\n  Pass {:?} only ran in the new log", new_pass.name)?;
+                    continue;
+                }
+                (None, None) => continue,
+            };
+
+            let diffs = diff_compilations(old_pass, new_pass);
+            if diffs.is_empty() {
+                identical += 1;
+                continue;
+            }
+
+            writeln!(debugout, "\n  Pass {:?} differs{}", old_pass.name,
+                                 if diverged { "" } else { " (first divergence)" })?;
+            parse_block_diffs(debugout, &diffs)?;
+            diverged = true;
+        }
+
+        writeln!(debugout, "\n  {} passes are identical", identical)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instr(id: u32, opcode: &str) -> MirInstruction {
+        MirInstruction { id, opcode: opcode.to_string(), ty: "Int32".to_string(), ..Default::default() }
+    }
+
+    fn signs(lines: &[(char, &MirInstruction)]) -> Vec<(char, u32)> {
+        lines.iter().map(|(sign, i)| (*sign, i.id)).collect()
+    }
+
+    #[test]
+    fn operands_without_ids() {
+        assert_eq!(without_ids(&instr(9, "add constant3 parameter1")), "add constant parameter");
+        assert_eq!(without_ids(&instr(3, "unbox parameter1 (int32)")), "unbox parameter (int32)");
+        assert_eq!(without_ids(&instr(1, "parameter 0")), "parameter 0");
+        assert_eq!(without_ids(&instr(0, "parameter THIS_SLOT")), "parameter THIS_SLOT");
+    }
+
+    #[test]
+    fn shifted_ids() {
+        let old = [instr(2, "constant 1"), instr(3, "add parameter1 constant2"), instr(4, "return add3")];
+        let new = [instr(2, "constant 0"), instr(3, "constant 1"),
+                   instr(4, "add parameter1 constant3"), instr(5, "return add4")];
+
+        let old = old.iter().collect::<Vec<_>>();
+        let new = new.iter().collect::<Vec<_>>();
+        assert_eq!(signs(&diff_instructions(&old, &new)), [('+', 2)]);
+    }
+
+    /// Length of the longest common subsequence, the quadratic way
+    fn lcs(old: &[u32], new: &[u32]) -> usize {
+        let mut table = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in 0..old.len() {
+            for j in 0..new.len() {
+                table[i + 1][j + 1] = if old[i] == new[j] {
+                    table[i][j] + 1
+                } else {
+                    table[i][j + 1].max(table[i + 1][j])
+                };
+            }
+        }
+        table[old.len()][new.len()]
+    }
+
+    /// Entries marked as kept, checking that they pair up
+    fn kept(old: &[u32], new: &[u32]) -> usize {
+        let mut old_kept = vec![false; old.len()];
+        let mut new_kept = vec![false; new.len()];
+        common(old, new, &mut old_kept, &mut new_kept);
+
+        let old_common = old.iter().zip(old_kept).filter(|(_, k)| *k).map(|(e, _)| *e).collect::<Vec<_>>();
+        let new_common = new.iter().zip(new_kept).filter(|(_, k)| *k).map(|(e, _)| *e).collect::<Vec<_>>();
+        assert_eq!(old_common, new_common);
+        old_common.len()
+    }
+
+    #[test]
+    fn longest_common_subsequence() {
+        // Small alphabets so that the lists have plenty in common
+        let mut seed = 1u32;
+        let mut random = |below: u32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) % below
+        };
+
+        for _ in 0..500 {
+            let alphabet = 1 + random(4);
+            let old = (0..random(20)).map(|_| random(alphabet)).collect::<Vec<_>>();
+            let new = (0..random(20)).map(|_| random(alphabet)).collect::<Vec<_>>();
+            assert_eq!(kept(&old, &new), lcs(&old, &new), "{:?} {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn large_blocks() {
+        let old = (0..50_000).collect::<Vec<_>>();
+        let new = (0..50_000).filter(|e| e % 1000 != 0).chain([1_000_000]).collect::<Vec<_>>();
+        assert_eq!(kept(&old, &new), 49_950);
+
+        // Nothing in common, and too many differences to look any further
+        let new = (50_000..100_000).collect::<Vec<_>>();
+        assert_eq!(kept(&old, &new), 0);
+    }
+}
@@ -0,0 +1,138 @@
+//! Graphviz DOT export of the control-flow graph of a pass.
+//!
+//! Every pass becomes its own `digraph`, with one record node per block
+//! listing its instructions. Render with eg. `dot -Tsvg -O iongraph.dot`.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::{Function, IonLog, Ir, Pass};
+
+/// Escape a string for use inside a quoted DOT string
+fn quote(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape a string for use inside a record label, where braces, pipes and
+/// angle brackets are field syntax
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Render the block graph of a single pass. LIR blocks have no successors of
+/// their own, so the edges always come from the MIR graph.
+pub fn parse_pass(debugout: &mut impl Write,
+                  title: &str, pass: &Pass, ir: Ir) -> io::Result<()> {
+
+    writeln!(debugout, "digraph \"{}\" {{", quote(title))?;
+    writeln!(debugout, "    label=\"{}\";", quote(title))?;
+    writeln!(debugout, "    labelloc=t;")?;
+    writeln!(debugout, "    node [shape=record, fontname=\"monospace\"];\n")?;
+
+    // Instruction lines of each block, keyed by the block number
+    let mut labels: Vec<(u32, Vec<String>)> = Vec::new();
+
+    match (&pass.lir, ir) {
+        (Some(lir), Ir::Lir) => {
+            for block in lir.blocks.iter().filter(|b| b.malformed.is_none()) {
+                let lines = block.instructions.iter().map(|instr| {
+                    match instr.malformed {
+                        Some(_) => escape_record("???: <malformed>"),
+                        None => format!("{}: {}", instr.id, escape_record(&instr.opcode)),
+                    }
+                });
+                labels.push((block.number, lines.collect()));
+            }
+        }
+        _ => {
+            for block in pass.mir.blocks.iter().filter(|b| b.malformed.is_none()) {
+                let lines = block.instructions.iter().map(|instr| {
+                    match instr.malformed {
+                        Some(_) => escape_record("???: <malformed>"),
+                        None => format!("{}: {} : {}", instr.id,
+                                        escape_record(&instr.opcode),
+                                        escape_record(&instr.ty)),
+                    }
+                });
+                labels.push((block.number, lines.collect()));
+            }
+        }
+    }
+
+    for (number, lines) in labels.iter() {
+        let mut label = format!("Block#{}|", number);
+        for line in lines.iter() {
+            label += line;
+            label += "\\l";
+        }
+
+        writeln!(debugout, "    block{} [label=\"{{{}}}\"];", number, label)?;
+    }
+
+    writeln!(debugout)?;
+
+    let successors = pass.mir.blocks.iter()
+                                    .filter(|b| b.malformed.is_none())
+                                    .map(|b| (b.number, &b.successors))
+                                    .collect::<HashMap<_, _>>();
+
+    for (number, _) in labels.iter() {
+        let successors = match successors.get(number) {
+            Some(successors) => successors,
+            None => continue,
+        };
+
+        // Same convention as the text dump: the first of two successors is
+        // taken when the test is true
+        for (idx, succ) in successors.iter().enumerate() {
+            if successors.len() == 2 {
+                writeln!(debugout, "    block{} -> block{} [label=\"{}\"];",
+                                     number, succ, if idx == 0 { "T" } else { "F" })?;
+            } else {
+                writeln!(debugout, "    block{} -> block{};", number, succ)?;
+            }
+        }
+    }
+
+    writeln!(debugout, "}}")?;
+    Ok(())
+}
+
+/// Render every pass of a function as a separate digraph
+pub fn parse_function(debugout: &mut impl Write, func: &Function, ir: Ir) -> io::Result<()> {
+
+    if func.malformed.is_some() {
+        return Ok(());
+    }
+
+    for pass in func.passes.iter().filter(|p| p.malformed.is_none()) {
+
+        // No LIR yet for this pass, and that is all we were asked for
+        if ir == Ir::Lir && pass.lir.is_none() {
+            continue;
+        }
+
+        let title = format!("{} : {}", func.name, pass.name);
+        parse_pass(debugout, &title, pass, ir)?;
+        writeln!(debugout)?;
+    }
+
+    Ok(())
+}
+
+/// Render every pass of every function as a separate digraph
+pub fn parse_graph(debugout: &mut impl Write, iondata: &IonLog, ir: Ir) -> io::Result<()> {
+
+    for func in iondata.functions.iter() {
+        parse_function(debugout, func, ir)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+//! Errors that can happen while loading an `ion.json` file or writing the
+//! dump out.
+
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The ion.json file could not be read
+    Read { path: String, source: std::io::Error },
+
+    /// The ion.json file is not valid JSON, usually because it is truncated
+    Syntax(serde_json::Error),
+
+    /// The JSON does not look like what IonMonkey writes. `path` points at the
+    /// offending field, eg. `functions[3].passes[12].mir.blocks[5].instructions[7].opcode`
+    Schema { path: String, source: serde_json::Error },
+
+    /// The output could not be written
+    Write { path: String, source: std::io::Error },
+
+    /// The functions or passes asked for on the command line do not exist
+    Select(String),
+
+    /// The terminal could not be set up or drawn on by the `tui` subcommand
+    Terminal(std::io::Error),
+
+    /// The threads rendering the functions could not be started
+    Threads(rayon::ThreadPoolBuildError),
+}
+
+impl Error {
+
+    /// Process exit code for this class of error, so that scripts can tell
+    /// them apart. 2 is left to clap for bad command lines.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Read { .. }   => 3,
+            Error::Syntax(..)    => 4,
+            Error::Schema { .. } => 5,
+            Error::Write { .. }  => 6,
+            Error::Select(..)    => 7,
+            Error::Terminal(..)  => 8,
+            Error::Threads(..)   => 9,
+        }
+    }
+
+    /// Classify an error coming out of the deserializer
+    pub(crate) fn from_json(err: serde_path_to_error::Error<serde_json::Error>) -> Error {
+        let path = err.path().to_string();
+        let source = err.into_inner();
+
+        match source.classify() {
+            serde_json::error::Category::Data => Error::Schema { path, source },
+            _ => Error::Syntax(source),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Read { path, source } =>
+                write!(f, "Not able to read json file {}: {}", path, source),
+            Error::Syntax(source) =>
+                write!(f, "Not able to parse ion.json: {}", source),
+            Error::Schema { path, source } =>
+                write!(f, "Invalid ion logs json file encountered at {}: {}", path, source),
+            Error::Write { path, source } =>
+                write!(f, "unable to write output {}: {}", path, source),
+            Error::Select(message) =>
+                write!(f, "{}", message),
+            Error::Terminal(source) =>
+                write!(f, "unable to drive the terminal: {}", source),
+            Error::Threads(source) =>
+                write!(f, "unable to start the render threads: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Read { source, .. }   => Some(source),
+            Error::Syntax(source)        => Some(source),
+            Error::Schema { source, .. } => Some(source),
+            Error::Write { source, .. }  => Some(source),
+            Error::Select(..)            => None,
+            Error::Terminal(source)      => Some(source),
+            Error::Threads(source)       => Some(source),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! How often each MIR opcode shows up across a whole log, to spot which
+//! nodes dominate the compiled code.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::Function;
+
+/// What to order the opcodes by
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sort {
+    /// Most frequent first
+    Count,
+    /// Alphabetically
+    Name,
+}
+
+/// Opcode counts of one function, or of the whole log
+#[derive(Debug, Clone, Default)]
+pub struct Counts {
+    pub opcodes: HashMap<String, usize>,
+    pub total: usize,
+}
+
+impl Counts {
+
+    /// Count the opcodes of every pass of `func`. Malformed entries are not
+    /// counted.
+    pub fn of(func: &Function) -> Counts {
+
+        let mut counts = Counts::default();
+
+        let instructions = func.passes.iter()
+                                      .filter(|p| p.malformed.is_none())
+                                      .flat_map(|p| p.mir.blocks.iter())
+                                      .filter(|b| b.malformed.is_none())
+                                      .flat_map(|b| b.instructions.iter())
+                                      .filter(|i| i.malformed.is_none());
+
+        for instr in instructions {
+            let (opcode, _) = instr.opcode_and_operand();
+            *counts.opcodes.entry(opcode.to_string()).or_default() += 1;
+            counts.total += 1;
+        }
+
+        counts
+    }
+
+    pub fn merge(&mut self, other: &Counts) {
+        for (opcode, count) in other.opcodes.iter() {
+            *self.opcodes.entry(opcode.clone()).or_default() += count;
+        }
+        self.total += other.total;
+    }
+}
+
+/// The histogram of the whole log, and optionally of each function on its
+/// own
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    pub counts: Counts,
+    pub functions: usize,
+
+    /// Index, name and counts of every function, when a breakdown was asked
+    /// for
+    pub breakdown: Option<Vec<(usize, String, Counts)>>,
+}
+
+impl Histogram {
+
+    pub fn new(breakdown: bool) -> Histogram {
+        Histogram { breakdown: breakdown.then(Vec::new), ..Default::default() }
+    }
+
+    /// Add the (already narrowed down) passes of a function. `index` is its
+    /// position in the log.
+    pub fn add(&mut self, index: usize, func: &Function) {
+
+        if func.malformed.is_some() {
+            return;
+        }
+
+        let counts = Counts::of(func);
+        self.counts.merge(&counts);
+        self.functions += 1;
+
+        if let Some(breakdown) = &mut self.breakdown {
+            breakdown.push((index, func.name.clone(), counts));
+        }
+    }
+}
+
+fn parse_counts(debugout: &mut impl Write, counts: &Counts, sort: Sort) -> io::Result<()> {
+
+    let mut opcodes = counts.opcodes.iter().collect::<Vec<_>>();
+    match sort {
+        Sort::Count => opcodes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0))),
+        Sort::Name  => opcodes.sort_by(|a, b| a.0.cmp(b.0)),
+    }
+
+    writeln!(debugout, "  {:>8}  {:>6}  opcode", "count", "%")?;
+    for (opcode, count) in opcodes {
+        let percent = 100.0 * *count as f64 / counts.total.max(1) as f64;
+        writeln!(debugout, "  {:>8}  {:>5.1}%  {}", count, percent, opcode)?;
+    }
+
+    Ok(())
+}
+
+/// Write the histogram of the whole log, followed by the one of each
+/// function if there is a breakdown
+pub fn parse_histogram(debugout: &mut impl Write, histogram: &Histogram, sort: Sort) -> io::Result<()> {
+
+    writeln!(debugout, "Opcodes of {} function{}, {} instructions\n", histogram.functions,
+             if histogram.functions == 1 { "" } else { "s" }, histogram.counts.total)?;
+    parse_counts(debugout, &histogram.counts, sort)?;
+
+    for (index, name, counts) in histogram.breakdown.iter().flatten() {
+        writeln!(debugout, "\n\nOpcodes of Function #{}: {:?}, {} instructions\n",
+                 index, name, counts.total)?;
+        parse_counts(debugout, counts, sort)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,332 @@
+//! Self-contained HTML report, to click around the passes of a compilation
+//! instead of scrolling through the text dump.
+//!
+//! The report is written in three parts, [`parse_header`], one
+//! [`parse_function`] per function and [`parse_footer`], so that it can be
+//! produced while streaming. The function list and the pass selectors are
+//! filled in by the embedded script once the page has loaded, and nothing is
+//! fetched from the network.
+
+use std::io::{self, Write};
+
+use crate::{Function, IonLog, Ir, LirBlock, MirBlock, Options, Pass};
+
+const STYLE: &str = r#"
+body { margin: 0; font-family: sans-serif; display: flex; height: 100vh; }
+nav { width: 18em; overflow-y: auto; border-right: 1px solid #ccc; padding: 0.5em; flex-shrink: 0; }
+nav input { width: 100%; box-sizing: border-box; margin-bottom: 0.5em; }
+nav a { display: block; font-family: monospace; padding: 0.1em 0.2em; text-decoration: none; color: #036; }
+nav a.current { background: #def; }
+main { flex-grow: 1; overflow-y: auto; padding: 0 1em; }
+section.function { display: none; }
+section.function.current { display: block; }
+div.pass { display: none; }
+div.pass.current { display: block; }
+details { margin: 0.3em 0; border-left: 3px solid #9bd; padding-left: 0.5em; }
+summary { font-family: monospace; font-weight: bold; cursor: pointer; }
+table { border-collapse: collapse; font-family: monospace; }
+td { padding: 0 0.8em 0 0; white-space: pre; vertical-align: top; }
+td.id { text-align: right; color: #666; }
+td.type { color: #484; }
+td.flow { color: #666; }
+.malformed { color: #b00; }
+.succ { font-family: monospace; margin: 0.2em 0 0.4em; }
+[data-v] { cursor: default; }
+.hl { background: #fe8; }
+:target > summary { background: #def; }
+"#;
+
+const SCRIPT: &str = r##"
+document.addEventListener("DOMContentLoaded", () => {
+  const nav = document.querySelector("nav");
+  const filter = nav.querySelector("input");
+  const functions = [...document.querySelectorAll("section.function")];
+  const links = functions.map(section => {
+    const link = document.createElement("a");
+    link.href = "#" + section.id;
+    link.textContent = section.dataset.index + ": " + section.dataset.name;
+    nav.appendChild(link);
+    return link;
+  });
+
+  const show = (section, pass) => {
+    functions.forEach((f, i) => {
+      f.classList.toggle("current", f === section);
+      links[i].classList.toggle("current", f === section);
+    });
+    const select = section.querySelector("select");
+    if (pass !== undefined) select.value = pass;
+    section.querySelectorAll("div.pass").forEach(p =>
+      p.classList.toggle("current", p.dataset.pass === select.value));
+  };
+
+  functions.forEach(section => {
+    section.querySelector("select").addEventListener("change", () => show(section));
+  });
+
+  filter.addEventListener("input", () => {
+    const text = filter.value.toLowerCase();
+    links.forEach(link => {
+      link.style.display = link.textContent.toLowerCase().includes(text) ? "" : "none";
+    });
+  });
+
+  // Block links lead to another pass or function only when followed by
+  // hand, but the target still has to be shown and opened
+  const follow = () => {
+    const target = location.hash && document.getElementById(location.hash.slice(1));
+    if (!target) return;
+    const section = target.closest("section.function");
+    const pass = target.closest("div.pass");
+    show(section, pass ? pass.dataset.pass : undefined);
+    if (target.tagName === "DETAILS") target.open = true;
+    target.scrollIntoView();
+  };
+  window.addEventListener("hashchange", follow);
+  if (functions.length) {
+    if (location.hash) follow(); else show(functions[0]);
+  }
+
+  // Hovering an instruction id highlights its definition and all its uses
+  // within the same pass
+  document.addEventListener("mouseover", event => {
+    const v = event.target.closest("[data-v]");
+    document.querySelectorAll(".hl").forEach(e => e.classList.remove("hl"));
+    if (!v) return;
+    v.closest("div.pass").querySelectorAll(`[data-v="${v.dataset.v}"]`)
+      .forEach(e => e.classList.add("hl"));
+  });
+});
+"##;
+
+/// Escape text for use in HTML content and quoted attributes
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `v12` that lights up all the other mentions of 12 when hovered
+fn value(id: u32) -> String {
+    format!("<span data-v=\"{}\">v{}</span>", id, id)
+}
+
+/// Link to a block of the same pass
+fn block_link(anchor: &str, number: u32) -> String {
+    format!("<a href=\"#{}-b{}\">Block#{}</a>", anchor, number, number)
+}
+
+/// Everything before the first function: the styles, the script and the
+/// navigation pane
+pub fn parse_header(debugout: &mut impl Write, title: &str) -> io::Result<()> {
+
+    writeln!(debugout, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(debugout, "<title>{}</title>", escape(title))?;
+    writeln!(debugout, "<style>{}</style>\n<script>{}</script>\n</head>", STYLE, SCRIPT)?;
+    writeln!(debugout, "<body>\n<nav><input type=\"search\" placeholder=\"Filter functions\"></nav>\n<main>")?;
+
+    Ok(())
+}
+
+/// Closes what [`parse_header`] opened
+pub fn parse_footer(debugout: &mut impl Write) -> io::Result<()> {
+    writeln!(debugout, "</main>\n</body>\n</html>")
+}
+
+fn parse_mir_blocks(debugout: &mut impl Write, anchor: &str,
+                    blocks: &[MirBlock], options: &Options) -> io::Result<()> {
+
+    for block in blocks.iter() {
+        if let Some(reason) = &block.malformed {
+            writeln!(debugout, "<p class=\"malformed\">Block#?? &lt;malformed block: {}&gt;</p>",
+                     escape(reason))?;
+            continue;
+        }
+
+        let mut notes = block.attributes.clone();
+        if block.loop_depth > 0 {
+            notes.push(format!("depth {}", block.loop_depth));
+        }
+
+        write!(debugout, "<details open id=\"{}-b{}\"><summary>Block#{}", anchor,
+               block.number, block.number)?;
+        if !notes.is_empty() {
+            write!(debugout, " [{}]", escape(&notes.join(", ")))?;
+        }
+        if !block.predecessors.is_empty() {
+            let preds = block.predecessors.iter()
+                                          .map(|&p| block_link(anchor, p))
+                                          .collect::<Vec<_>>();
+            write!(debugout, " preds: {}", preds.join(", "))?;
+        }
+        writeln!(debugout, "</summary>\n<table>")?;
+
+        for instr in block.instructions.iter() {
+            if let Some(reason) = &instr.malformed {
+                writeln!(debugout, "<tr><td class=\"id\">???</td><td class=\"malformed\" colspan=\"3\">\
+                                    &lt;malformed instruction: {}&gt;</td></tr>", escape(reason))?;
+                continue;
+            }
+
+            let (opcode, operand) = instr.opcode_and_operand();
+            write!(debugout, "<tr><td class=\"id\">{}</td><td>{}</td><td>{}</td><td class=\"type\">{}</td>",
+                   value(instr.id), escape(opcode), escape(operand), escape(&instr.ty))?;
+
+            // Without the inputs and uses there would be nothing to hover
+            let mut flow = Vec::new();
+            for (label, ids) in [("inputs", &instr.inputs), ("uses", &instr.uses),
+                                 ("mem", &instr.mem_inputs)] {
+                if !ids.is_empty() {
+                    let ids = ids.iter().map(|&id| value(id)).collect::<Vec<_>>();
+                    flow.push(format!("{}: {}", label, ids.join(", ")));
+                }
+            }
+            if options.dataflow && !instr.attributes.is_empty() {
+                flow.push(format!("[{}]", escape(&instr.attributes.join(", "))));
+            }
+            writeln!(debugout, "<td class=\"flow\">{}</td></tr>", flow.join("  "))?;
+        }
+
+        writeln!(debugout, "</table>")?;
+
+        if !block.successors.is_empty() {
+            let succs = block.successors.iter()
+                                        .map(|&s| block_link(anchor, s))
+                                        .collect::<Vec<_>>();
+            writeln!(debugout, "<div class=\"succ\">Successors: {}</div>", succs.join(" "))?;
+        }
+
+        writeln!(debugout, "</details>")?;
+    }
+
+    Ok(())
+}
+
+fn parse_lir_blocks(debugout: &mut impl Write, anchor: &str,
+                    blocks: &[LirBlock]) -> io::Result<()> {
+
+    for block in blocks.iter() {
+        if let Some(reason) = &block.malformed {
+            writeln!(debugout, "<p class=\"malformed\">Block#?? &lt;malformed block: {}&gt;</p>",
+                     escape(reason))?;
+            continue;
+        }
+
+        writeln!(debugout, "<details open id=\"{}-lb{}\"><summary>Block#{}</summary>\n<table>",
+                 anchor, block.number, block.number)?;
+
+        for instr in block.instructions.iter() {
+            if let Some(reason) = &instr.malformed {
+                writeln!(debugout, "<tr><td class=\"id\">???</td><td class=\"malformed\">\
+                                    &lt;malformed instruction: {}&gt;</td></tr>", escape(reason))?;
+                continue;
+            }
+
+            let defs = instr.defs.iter().map(|&v| value(v)).collect::<Vec<_>>();
+            write!(debugout, "<tr><td class=\"id\">{}</td><td>{}</td>", instr.id, escape(&instr.opcode))?;
+            match defs.is_empty() {
+                true  => writeln!(debugout, "</tr>")?,
+                false => writeln!(debugout, "<td class=\"flow\">defs: {}</td></tr>", defs.join(", "))?,
+            }
+        }
+
+        writeln!(debugout, "</table>\n</details>")?;
+    }
+
+    Ok(())
+}
+
+fn parse_pass(debugout: &mut impl Write, anchor: &str,
+              pass: &Pass, options: &Options) -> io::Result<()> {
+
+    let ir = options.ir;
+
+    if ir != Ir::Lir {
+        if ir == Ir::Both {
+            writeln!(debugout, "<h4>MIR</h4>")?;
+        }
+        parse_mir_blocks(debugout, anchor, &pass.mir.blocks, options)?;
+    }
+
+    if ir != Ir::Mir {
+        if let Some(lir) = &pass.lir {
+            if ir == Ir::Both {
+                writeln!(debugout, "<h4>LIR</h4>")?;
+            }
+            parse_lir_blocks(debugout, anchor, &lir.blocks)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// One function, with a selector to flip through its passes. `index` is its
+/// position in the log, which the anchors are made of.
+pub fn parse_function(debugout: &mut impl Write, index: usize,
+                      func: &Function, options: &Options) -> io::Result<()> {
+
+    if let Some(reason) = &func.malformed {
+        return writeln!(debugout, "<section class=\"function\" id=\"f{}\" data-index=\"{}\" \
+                                   data-name=\"&lt;malformed&gt;\"><select hidden></select>\
+                                   <p class=\"malformed\">&lt;malformed function: {}&gt;</p></section>",
+                        index, index, escape(reason));
+    }
+
+    writeln!(debugout, "<section class=\"function\" id=\"f{}\" data-index=\"{}\" data-name=\"{}\">",
+             index, index, escape(&func.name))?;
+    writeln!(debugout, "<h2>{}</h2>", escape(&func.name))?;
+
+    // Same as the text dump, passes without LIR have nothing to show then
+    let passes = func.passes.iter()
+                            .filter(|p| !(options.ir == Ir::Lir && p.malformed.is_none() &&
+                                          p.lir.is_none()))
+                            .collect::<Vec<_>>();
+
+    // Passes are numbered as in the log, whatever `--pass` kept
+    writeln!(debugout, "<label>After Ion Phase <select>")?;
+    for pass in passes.iter() {
+        let name = match &pass.malformed {
+            Some(_) => "<malformed pass>",
+            None => &pass.name,
+        };
+        writeln!(debugout, "<option value=\"{}\">{}: {}</option>", pass.index, pass.index,
+                 escape(name))?;
+    }
+    writeln!(debugout, "</select></label>")?;
+
+    for pass in passes.iter() {
+        let anchor = format!("f{}-p{}", index, pass.index);
+        writeln!(debugout, "<div class=\"pass\" id=\"{}\" data-pass=\"{}\">", anchor, pass.index)?;
+
+        match &pass.malformed {
+            Some(reason) => writeln!(debugout, "<p class=\"malformed\">&lt;malformed pass: {}&gt;</p>",
+                                     escape(reason))?,
+            None => parse_pass(debugout, &anchor, pass, options)?,
+        }
+
+        writeln!(debugout, "</div>")?;
+    }
+
+    writeln!(debugout, "</section>")
+}
+
+/// The whole report in one go
+pub fn parse_graph(debugout: &mut impl Write,
+                   iondata: &IonLog, options: &Options) -> io::Result<()> {
+
+    parse_header(debugout, "iongraph")?;
+
+    for (index, func) in iondata.functions.iter().enumerate() {
+        parse_function(debugout, index, func, options)?;
+    }
+
+    parse_footer(debugout)
+}
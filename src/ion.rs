@@ -0,0 +1,185 @@
+//! Typed model of the `ion.json` file written by IonMonkey's JSON spewer
+//! (`js/src/jit/JSONSpewer.cpp`).
+//!
+//! Only the fields that are always emitted are required, everything else
+//! falls back to an empty default so that logs from older or newer engine
+//! builds still load.
+
+use serde::{Deserialize, Deserializer};
+
+/// The whole `ion.json` file
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct IonLog {
+    /// Every function that was Ion compiled, in compilation order
+    pub functions: Vec<Function>,
+}
+
+/// A single Ion compilation of a script
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Function {
+    /// Name of the script, usually `file:line`
+    pub name: String,
+
+    /// The optimization passes that ran, in order
+    #[serde(deserialize_with = "numbered")]
+    pub passes: Vec<Pass>,
+
+    /// Set by the lenient loader when this entry could not be interpreted,
+    /// with the reason. Everything else is left defaulted in that case.
+    #[serde(skip)]
+    pub malformed: Option<String>,
+}
+
+/// Snapshot of the graph after one optimization pass
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Pass {
+    /// Name of the pass, eg. `GVN`
+    pub name: String,
+
+    /// Position of the pass in the function as it was logged, which stays
+    /// the same when only some of the passes are kept
+    #[serde(skip)]
+    pub index: usize,
+
+    /// The MIR graph after this pass
+    pub mir: Mir,
+
+    /// The LIR graph after this pass. Only present once lowering has run.
+    #[serde(default)]
+    pub lir: Option<Lir>,
+
+    /// See [`Function::malformed`]
+    #[serde(skip)]
+    pub malformed: Option<String>,
+}
+
+/// Deserialize the passes of a function, telling each its position
+fn numbered<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Pass>, D::Error> {
+
+    let mut passes = Vec::<Pass>::deserialize(deserializer)?;
+    for (index, pass) in passes.iter_mut().enumerate() {
+        pass.index = index;
+    }
+
+    Ok(passes)
+}
+
+/// MIR graph of a pass
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Mir {
+    pub blocks: Vec<MirBlock>,
+}
+
+/// A MIR basic block
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct MirBlock {
+    /// Block id, which is what successors and predecessors refer to
+    pub number: u32,
+
+    /// `loopheader`, `backedge` and/or `splitedge`
+    #[serde(default)]
+    pub attributes: Vec<String>,
+
+    #[serde(default, rename = "loopDepth")]
+    pub loop_depth: u32,
+
+    #[serde(default)]
+    pub predecessors: Vec<u32>,
+
+    pub successors: Vec<u32>,
+
+    /// Phis first, followed by the rest of the instructions
+    pub instructions: Vec<MirInstruction>,
+
+    /// See [`Function::malformed`]
+    #[serde(skip)]
+    pub malformed: Option<String>,
+}
+
+/// A MIR definition
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct MirInstruction {
+    pub id: u32,
+
+    /// Opcode name, followed by its operands separated by a space
+    pub opcode: String,
+
+    #[serde(default)]
+    pub attributes: Vec<String>,
+
+    /// Ids of the operands of this instruction
+    #[serde(default)]
+    pub inputs: Vec<u32>,
+
+    /// Ids of the instructions using this one
+    #[serde(default)]
+    pub uses: Vec<u32>,
+
+    /// Id of the instruction this one has a memory dependency on, if any
+    #[serde(default, rename = "memInputs")]
+    pub mem_inputs: Vec<u32>,
+
+    /// Result type, prefixed by the computed range if there is one
+    #[serde(default, rename = "type")]
+    pub ty: String,
+
+    /// See [`Function::malformed`]
+    #[serde(skip)]
+    pub malformed: Option<String>,
+}
+
+/// LIR graph of a pass
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Lir {
+    pub blocks: Vec<LirBlock>,
+}
+
+/// A LIR basic block. These share their number with the MIR block they were
+/// lowered from, and do not carry successors of their own.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LirBlock {
+    pub number: u32,
+
+    /// Phis first, followed by the rest of the instructions
+    pub instructions: Vec<LirInstruction>,
+
+    /// See [`Function::malformed`]
+    #[serde(skip)]
+    pub malformed: Option<String>,
+}
+
+/// A LIR instruction
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LirInstruction {
+    pub id: u32,
+
+    /// The whole `LNode::dump()` output, operands included
+    pub opcode: String,
+
+    /// Virtual registers defined by this instruction
+    #[serde(default)]
+    pub defs: Vec<u32>,
+
+    /// See [`Function::malformed`]
+    #[serde(skip)]
+    pub malformed: Option<String>,
+}
+
+impl MirBlock {
+
+    /// Whether the block has the given attribute, eg. `loopheader`
+    pub fn has_attribute(&self, attribute: &str) -> bool {
+        self.attributes.iter().any(|a| a == attribute)
+    }
+}
+
+impl MirInstruction {
+
+    /// Split the opcode field into the opcode name and its operands
+    pub fn opcode_and_operand(&self) -> (&str, &str) {
+        match self.opcode.split_once(' ') {
+            Some((opcode, operand)) => (opcode, operand),
+            None => (&self.opcode, "")
+        }
+    }
+}
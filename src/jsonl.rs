@@ -0,0 +1,133 @@
+//! JSON lines export, one record per instruction, for `jq`, pandas and CI
+//! scripts.
+//!
+//! Every record carries the function, pass and block it belongs to, so that
+//! the lines can be filtered and grouped on their own. Malformed entries are
+//! left out.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::{Function, IonLog, Ir, Pass};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Record<'a> {
+    /// `mir` or `lir`
+    pub ir: &'static str,
+
+    pub function: &'a str,
+
+    /// Position of the function in the log, as used by `--function`
+    pub function_index: usize,
+
+    /// Position of the pass in the function, as used by `--pass`
+    pub pass: usize,
+    pub pass_name: &'a str,
+    pub block: u32,
+    pub id: u32,
+    pub opcode: &'a str,
+    pub operand: &'a str,
+
+    /// MIR only
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ty: Option<&'a str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<&'a [u32]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uses: Option<&'a [u32]>,
+
+    /// LIR only, the virtual registers the instruction defines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defs: Option<&'a [u32]>,
+}
+
+fn write_record(debugout: &mut impl Write, record: &Record) -> io::Result<()> {
+    serde_json::to_writer(&mut *debugout, record)?;
+    writeln!(debugout)
+}
+
+/// The records of one pass, MIR first
+pub fn parse_pass(debugout: &mut impl Write, index: usize, func: &Function,
+                  pass: &Pass, ir: Ir) -> io::Result<()> {
+
+    if pass.malformed.is_some() {
+        return Ok(());
+    }
+
+    let record = |ir, block, id, opcode, operand| Record {
+        ir,
+        function: &func.name,
+        function_index: index,
+        pass: pass.index,
+        pass_name: &pass.name,
+        block,
+        id,
+        opcode,
+        operand,
+        ty: None,
+        inputs: None,
+        uses: None,
+        defs: None,
+    };
+
+    if ir != Ir::Lir {
+        for block in pass.mir.blocks.iter().filter(|b| b.malformed.is_none()) {
+            for instr in block.instructions.iter().filter(|i| i.malformed.is_none()) {
+                let (opcode, operand) = instr.opcode_and_operand();
+
+                write_record(debugout, &Record {
+                    ty: Some(&instr.ty),
+                    inputs: Some(&instr.inputs),
+                    uses: Some(&instr.uses),
+                    ..record("mir", block.number, instr.id, opcode, operand)
+                })?;
+            }
+        }
+    }
+
+    if ir != Ir::Mir {
+        let blocks = pass.lir.iter().flat_map(|lir| lir.blocks.iter());
+
+        for block in blocks.filter(|b| b.malformed.is_none()) {
+            // The whole `LNode::dump()` output, there is no telling the
+            // operands apart
+            for instr in block.instructions.iter().filter(|i| i.malformed.is_none()) {
+                write_record(debugout, &Record {
+                    defs: Some(&instr.defs),
+                    ..record("lir", block.number, instr.id, &instr.opcode, "")
+                })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The records of every pass of a function. `index` is its position in the
+/// log.
+pub fn parse_function(debugout: &mut impl Write, index: usize,
+                      func: &Function, ir: Ir) -> io::Result<()> {
+
+    if func.malformed.is_some() {
+        return Ok(());
+    }
+
+    for pass in func.passes.iter() {
+        parse_pass(debugout, index, func, pass, ir)?;
+    }
+
+    Ok(())
+}
+
+pub fn parse_graph(debugout: &mut impl Write,
+                   iondata: &IonLog, ir: Ir) -> io::Result<()> {
+
+    for (index, func) in iondata.functions.iter().enumerate() {
+        parse_function(debugout, index, func, ir)?;
+    }
+
+    Ok(())
+}
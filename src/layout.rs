@@ -0,0 +1,360 @@
+//! Layered layout of a block graph, so that it can be drawn without Graphviz.
+//!
+//! This is the usual Sugiyama scheme, kept simple:
+//!
+//! 1. Loop backedges are taken out, so that what is left flows downwards.
+//!    Those the caller already knows about are used as is, and any other
+//!    cycle is broken with a depth first search.
+//! 2. Every node goes on the layer below the lowest of its predecessors, so a
+//!    loop body always hangs under its loop header, and the blocks a loop
+//!    exits to go below the whole loop.
+//! 3. Edges spanning several layers get a dummy node on each layer in
+//!    between, and the nodes of each layer are reordered by the barycenter
+//!    of their neighbours to cut down on crossings.
+//! 4. Nodes are moved towards the center of their neighbours, and the
+//!    backedges are routed around the right side of the graph.
+
+/// A position, in pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+
+    /// Known to close a loop, eg. from a block marked as `backedge`
+    pub back: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    /// Top left corner of every node
+    pub nodes: Vec<Point>,
+
+    /// The points each edge goes through, from its source to its target
+    pub routes: Vec<Vec<Point>>,
+
+    /// Which edges were laid out as backedges, going up
+    pub back: Vec<bool>,
+
+    pub width: f64,
+    pub height: f64,
+}
+
+const MARGIN: f64    = 20.0;
+const NODE_GAP: f64  = 30.0;
+const LAYER_GAP: f64 = 50.0;
+const BACK_GAP: f64  = 12.0;
+
+/// Find the edges to leave out so that the rest of the graph has no cycle
+fn backedges(count: usize, edges: &[Edge]) -> Vec<bool> {
+
+    let mut back = edges.iter().map(|e| e.back).collect::<Vec<_>>();
+
+    let mut out = vec![Vec::new(); count];
+    for (idx, edge) in edges.iter().enumerate() {
+        out[edge.from].push(idx);
+    }
+
+    // 0: not seen yet, 1: on the stack, 2: done. Starting from node 0 first
+    // as that is the entry block.
+    let mut state = vec![0u8; count];
+
+    for root in 0..count {
+        if state[root] != 0 {
+            continue;
+        }
+
+        let mut stack = vec![(root, 0)];
+        state[root] = 1;
+
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            match out[node].get(*next) {
+                None => {
+                    state[node] = 2;
+                    stack.pop();
+                }
+                Some(&idx) => {
+                    *next += 1;
+                    let to = edges[idx].to;
+                    if back[idx] {
+                        continue;
+                    }
+                    match state[to] {
+                        0 => {
+                            state[to] = 1;
+                            stack.push((to, 0));
+                        }
+                        1 => back[idx] = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    back
+}
+
+/// Nodes of the loop closed by each backedge: its header, and everything that
+/// reaches the backedge without going through the header
+fn loops(count: usize, edges: &[Edge], back: &[bool]) -> Vec<Vec<bool>> {
+
+    let mut preds = vec![Vec::new(); count];
+    for edge in edges.iter() {
+        preds[edge.to].push(edge.from);
+    }
+
+    edges.iter().zip(back).filter(|(_, &back)| back).map(|(edge, _)| {
+        let mut body = vec![false; count];
+        body[edge.to] = true;
+
+        let mut todo = vec![edge.from];
+        while let Some(node) = todo.pop() {
+            if !body[node] {
+                body[node] = true;
+                todo.extend(preds[node].iter());
+            }
+        }
+
+        body
+    }).collect()
+}
+
+/// Layer of every node, along the longest path from the top. The blocks a
+/// loop exits to are pushed below the whole loop body, so that loops read
+/// top to bottom like the source.
+fn layers(count: usize, edges: &[Edge], back: &[bool]) -> Vec<usize> {
+
+    let mut out = vec![Vec::new(); count];
+    let mut forward = vec![0; count];
+    for (edge, _) in edges.iter().zip(back).filter(|(_, &back)| !back) {
+        forward[edge.to] += 1;
+        out[edge.from].push(edge.to);
+    }
+
+    let loops = loops(count, edges, back);
+    let mut floor = vec![0; count];
+    let mut layer = Vec::new();
+
+    // Each round can only push layers down, so this settles quickly on
+    // anything IonMonkey builds. Irreducible graphs give up after a while.
+    for _ in 0..=count {
+        layer = floor.clone();
+        let mut incoming = forward.clone();
+
+        let mut ready = (0..count).filter(|&n| incoming[n] == 0).collect::<Vec<_>>();
+        while let Some(node) = ready.pop() {
+            for &to in out[node].iter() {
+                layer[to] = layer[to].max(layer[node] + 1);
+                incoming[to] -= 1;
+                if incoming[to] == 0 {
+                    ready.push(to);
+                }
+            }
+        }
+
+        let mut settled = true;
+        for body in loops.iter() {
+            let bottom = (0..count).filter(|&n| body[n]).map(|n| layer[n]).max().unwrap_or(0);
+
+            for (from, to) in (0..count).flat_map(|n| out[n].iter().map(move |&to| (n, to))) {
+                if body[from] && !body[to] && layer[to] <= bottom && floor[to] <= bottom {
+                    floor[to] = bottom + 1;
+                    settled = false;
+                }
+            }
+        }
+
+        if settled {
+            break;
+        }
+    }
+
+    layer
+}
+
+/// Lay out nodes of the given sizes, `(width, height)`, joined by `edges`
+pub fn layout(sizes: &[(f64, f64)], edges: &[Edge]) -> Layout {
+
+    let count = sizes.len();
+    if count == 0 {
+        return Layout::default();
+    }
+
+    let back  = backedges(count, edges);
+    let layer = layers(count, edges, &back);
+
+    // The layered graph: the real nodes first, then the dummies
+    let mut vlayer = layer.clone();
+    let mut vwidth = sizes.iter().map(|s| s.0).collect::<Vec<_>>();
+    let mut preds  = vec![Vec::new(); count];
+    let mut succs  = vec![Vec::new(); count];
+
+    // Chain of vertices each forward edge goes through, dummies only
+    let mut chains = vec![Vec::new(); edges.len()];
+
+    for (idx, edge) in edges.iter().enumerate() {
+        if back[idx] {
+            continue;
+        }
+
+        let mut prev = edge.from;
+        for l in layer[edge.from] + 1..layer[edge.to] {
+            let dummy = vlayer.len();
+            vlayer.push(l);
+            vwidth.push(0.0);
+            preds.push(Vec::new());
+            succs.push(Vec::new());
+            chains[idx].push(dummy);
+
+            succs[prev].push(dummy);
+            preds[dummy].push(prev);
+            prev = dummy;
+        }
+
+        succs[prev].push(edge.to);
+        preds[edge.to].push(prev);
+    }
+
+    let nlayers = layer.iter().max().unwrap() + 1;
+    let mut order = vec![Vec::new(); nlayers];
+    for (v, &l) in vlayer.iter().enumerate() {
+        order[l].push(v);
+    }
+
+    // Crossing reduction, sweeping down and then up a few times
+    let mut pos = vec![0.0; vlayer.len()];
+    for row in order.iter() {
+        for (idx, &v) in row.iter().enumerate() {
+            pos[v] = idx as f64;
+        }
+    }
+
+    for _ in 0..4 {
+        for (range, neighbours) in [((1..nlayers).collect::<Vec<_>>(), &preds),
+                                    ((0..nlayers.saturating_sub(1)).rev().collect(), &succs)] {
+            for l in range {
+                let bary = |v: usize| match neighbours[v].len() {
+                    0 => pos[v],
+                    n => neighbours[v].iter().map(|&u| pos[u]).sum::<f64>() / n as f64,
+                };
+                let mut keyed = order[l].iter().map(|&v| (bary(v), v)).collect::<Vec<_>>();
+                keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+                order[l] = keyed.into_iter().map(|(_, v)| v).collect();
+
+                for (idx, &v) in order[l].iter().enumerate() {
+                    pos[v] = idx as f64;
+                }
+            }
+        }
+    }
+
+    // Horizontal placement: start packed to the left, then pull every vertex
+    // towards the middle of its neighbours without letting them overlap
+    let mut x = vec![0.0; vlayer.len()];
+    for row in order.iter() {
+        let mut left = 0.0;
+        for &v in row.iter() {
+            x[v] = left;
+            left += vwidth[v] + NODE_GAP;
+        }
+    }
+
+    for _ in 0..4 {
+        for (range, neighbours) in [((1..nlayers).collect::<Vec<_>>(), &preds),
+                                    ((0..nlayers.saturating_sub(1)).rev().collect(), &succs),
+                                    ((1..nlayers).collect::<Vec<_>>(), &preds)] {
+            for l in range {
+                let center = |v: usize| x[v] + vwidth[v] / 2.0;
+                let wanted = order[l].iter().map(|&v| match neighbours[v].len() {
+                    0 => x[v],
+                    n => neighbours[v].iter().map(|&u| center(u)).sum::<f64>() / n as f64
+                         - vwidth[v] / 2.0,
+                }).collect::<Vec<_>>();
+
+                let mut min = f64::MIN;
+                for (&v, wanted) in order[l].iter().zip(wanted) {
+                    x[v] = wanted.max(min);
+                    min = x[v] + vwidth[v] + NODE_GAP;
+                }
+            }
+        }
+    }
+
+    let shift = MARGIN - x.iter().cloned().fold(f64::MAX, f64::min);
+    for x in x.iter_mut() {
+        *x += shift;
+    }
+
+    // Vertical placement, every layer as tall as its tallest node
+    let mut top    = vec![MARGIN; nlayers];
+    let mut height = vec![0.0f64; nlayers];
+    for (node, &l) in layer.iter().enumerate() {
+        height[l] = height[l].max(sizes[node].1);
+    }
+    for l in 1..nlayers {
+        top[l] = top[l - 1] + height[l - 1] + LAYER_GAP;
+    }
+
+    let nodes = (0..count).map(|n| Point { x: x[n], y: top[layer[n]] })
+                          .collect::<Vec<_>>();
+
+    let mut width = (0..vlayer.len()).map(|v| x[v] + vwidth[v])
+                                     .fold(0.0, f64::max);
+    let bottom = top[nlayers - 1] + height[nlayers - 1];
+
+    // Edges leave from the bottom of their source, spread out if there are
+    // several, and come in at the top of their target
+    let mut outgoing = vec![0; count];
+    for (edge, _) in edges.iter().zip(back.iter()).filter(|(_, &back)| !back) {
+        outgoing[edge.from] += 1;
+    }
+
+    let mut leaving = vec![0; count];
+    let mut routes  = Vec::with_capacity(edges.len());
+    let mut backs   = 0;
+
+    for (idx, edge) in edges.iter().enumerate() {
+        let (from, to) = (nodes[edge.from], nodes[edge.to]);
+        let (fw, fh) = sizes[edge.from];
+        let (tw, _)  = sizes[edge.to];
+
+        if back[idx] {
+            // Around the right of everything in the layers it spans
+            let span = layer[edge.to].min(layer[edge.from])..=layer[edge.to].max(layer[edge.from]);
+            let right = (0..vlayer.len()).filter(|&v| span.contains(&vlayer[v]))
+                                         .map(|v| x[v] + vwidth[v])
+                                         .fold(0.0, f64::max);
+            backs += 1;
+            let lane = right + BACK_GAP * backs as f64;
+            width = width.max(lane);
+
+            let start = Point { x: from.x + fw, y: from.y + fh / 2.0 };
+            let end   = Point { x: to.x + tw, y: to.y + 10.0 };
+            routes.push(vec![start, Point { x: lane, y: start.y },
+                             Point { x: lane, y: end.y }, end]);
+            continue;
+        }
+
+        leaving[edge.from] += 1;
+        let spread = fw * leaving[edge.from] as f64 / (outgoing[edge.from] + 1) as f64;
+
+        let mut route = vec![Point { x: from.x + spread, y: from.y + fh }];
+        for &dummy in chains[idx].iter() {
+            let l = vlayer[dummy];
+            route.push(Point { x: x[dummy], y: top[l] });
+            route.push(Point { x: x[dummy], y: top[l] + height[l] });
+        }
+        route.push(Point { x: to.x + tw / 2.0, y: to.y });
+
+        routes.push(route);
+    }
+
+    Layout { nodes, routes, back, width: width + MARGIN, height: bottom + MARGIN }
+}
@@ -0,0 +1,335 @@
+//! Lenient loading of `ion.json` files.
+//!
+//! Instead of giving up on the first entry that does not match the model,
+//! every function, pass, block and instruction is deserialized on its own, and
+//! replaced by a placeholder (see [`Function::malformed`]) when that fails.
+//! Files cut short by a crashing process are closed up before parsing, so
+//! whatever was written before the crash can still be read.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{Error, Function, IonLog, LirBlock, LirInstruction, MirBlock,
+            MirInstruction, Pass, Result};
+
+/// An entry that had to be replaced by a placeholder
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// `function`, `pass`, `block` or `instruction`
+    pub kind: &'static str,
+
+    /// Path of the offending field
+    pub path: String,
+
+    pub message: String,
+}
+
+/// Everything the lenient loader had to paper over
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The file ended in the middle of the JSON
+    pub truncated: bool,
+
+    pub skipped: Vec<Diagnostic>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "skipped malformed {} at {}: {}", self.kind, self.path, self.message)
+    }
+}
+
+impl Report {
+
+    /// One line summary of how much was skipped, by kind
+    pub fn summary(&self) -> String {
+        let count = |kind| self.skipped.iter().filter(|d| d.kind == kind).count();
+
+        format!("{} malformed entries skipped ({} functions, {} passes, {} blocks, {} instructions){}",
+                self.skipped.len(), count("function"), count("pass"), count("block"),
+                count("instruction"),
+                if self.truncated { ", input was truncated" } else { "" })
+    }
+}
+
+trait Placeholder {
+    fn placeholder(reason: String) -> Self;
+}
+
+macro_rules! placeholder {
+    ($($ty: ty),*) => {
+        $(
+            impl Placeholder for $ty {
+                fn placeholder(reason: String) -> Self {
+                    Self { malformed: Some(reason), ..Default::default() }
+                }
+            }
+        )*
+    };
+}
+
+placeholder!(Function, Pass, MirBlock, MirInstruction, LirBlock, LirInstruction);
+
+/// Deserialize the contents of an `ion.json` file, skipping whatever cannot
+/// be interpreted. Only a file without a `functions` list is an error.
+pub fn from_str(contents: &str) -> Result<(IonLog, Report)> {
+
+    let mut report = Report::default();
+
+    let value = match serde_json::from_str(contents) {
+        Ok(value) => value,
+        Err(err) if err.is_eof() => {
+            report.truncated = true;
+            serde_json::from_str(&close_truncated(contents)).map_err(Error::Syntax)?
+        }
+        Err(err) => return Err(Error::Syntax(err)),
+    };
+
+    let log = ion_log(value, &mut report)?;
+    Ok((log, report))
+}
+
+/// Cut a truncated JSON document back to the last complete value and close
+/// all the arrays and objects that are still open at that point.
+pub(crate) fn close_truncated(contents: &str) -> String {
+
+    let mut stack     = Vec::new();
+    let mut in_string = false;
+    let mut escaped   = false;
+
+    // Byte offset to cut at and the number of containers open there
+    let mut cut = (0, 0);
+
+    for (idx, &byte) in contents.as_bytes().iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"'  => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => stack.push(byte),
+            b'}' | b']' => {
+                // Closing more than was opened, the document is over
+                if stack.pop().is_none() {
+                    break;
+                }
+                cut = (idx + 1, stack.len());
+            }
+            // Everything before a comma is a complete element
+            b',' => cut = (idx, stack.len()),
+            _ => {}
+        }
+    }
+
+    // Nothing is closed after the cut, so the containers open there are
+    // still at the bottom of the stack.
+    let mut closed = contents[..cut.0].to_string();
+    for &open in stack[..cut.1].iter().rev() {
+        closed.push(if open == b'{' { '}' } else { ']' });
+    }
+
+    closed
+}
+
+/// Remove the list at `pointer` from `value`, leaving an empty one behind so
+/// that the rest can be deserialized without it
+fn take_list(value: &mut Value, pointer: &str) -> Option<Vec<Value>> {
+    match value.pointer_mut(pointer) {
+        Some(list) if list.is_array() => {
+            match std::mem::replace(list, Value::Array(Vec::new())) {
+                Value::Array(list) => Some(list),
+                _ => unreachable!(),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Deserialize a single entry, or record why it could not be and return a
+/// placeholder instead
+fn entry<T>(value: Value, path: &str, kind: &'static str, report: &mut Report) -> T
+    where T: DeserializeOwned + Placeholder {
+
+    match serde_path_to_error::deserialize(value) {
+        Ok(entry) => entry,
+        Err(err) => {
+            let path = match err.path().to_string().as_str() {
+                "." => path.to_string(),
+                field => format!("{}.{}", path, field),
+            };
+            let message = err.into_inner().to_string();
+
+            report.skipped.push(Diagnostic { kind, path, message: message.clone() });
+            T::placeholder(message)
+        }
+    }
+}
+
+/// Run `parse` on each element of a list taken out with [`take_list`]
+fn each<T>(list: Option<Vec<Value>>, path: &str, report: &mut Report,
+           parse: fn(Value, String, &mut Report) -> T) -> Option<Vec<T>> {
+
+    Some(list?.into_iter()
+              .enumerate()
+              .map(|(idx, value)| parse(value, format!("{}[{}]", path, idx), report))
+              .collect())
+}
+
+fn ion_log(mut value: Value, report: &mut Report) -> Result<IonLog> {
+
+    let functions = take_list(&mut value, "/functions");
+
+    let mut log: IonLog = serde_path_to_error::deserialize(value).map_err(Error::from_json)?;
+    if let Some(functions) = each(functions, "functions", report, function) {
+        log.functions = functions;
+    }
+
+    Ok(log)
+}
+
+pub(crate) fn function(mut value: Value, path: String, report: &mut Report) -> Function {
+
+    let passes = take_list(&mut value, "/passes");
+
+    let mut func: Function = entry(value, &path, "function", report);
+    if func.malformed.is_none() {
+        if let Some(passes) = each(passes, &format!("{}.passes", path), report, pass) {
+            func.passes = passes;
+        }
+
+        for (index, pass) in func.passes.iter_mut().enumerate() {
+            pass.index = index;
+        }
+    }
+
+    func
+}
+
+fn pass(mut value: Value, path: String, report: &mut Report) -> Pass {
+
+    let mirblocks = take_list(&mut value, "/mir/blocks");
+    let lirblocks = take_list(&mut value, "/lir/blocks");
+
+    let mut pass: Pass = entry(value, &path, "pass", report);
+    if pass.malformed.is_none() {
+        if let Some(blocks) = each(mirblocks, &format!("{}.mir.blocks", path), report, mir_block) {
+            pass.mir.blocks = blocks;
+        }
+
+        if let Some(lir) = &mut pass.lir {
+            if let Some(blocks) = each(lirblocks, &format!("{}.lir.blocks", path), report, lir_block) {
+                lir.blocks = blocks;
+            }
+        }
+    }
+
+    pass
+}
+
+fn mir_block(mut value: Value, path: String, report: &mut Report) -> MirBlock {
+
+    let instructions = take_list(&mut value, "/instructions");
+
+    let mut block: MirBlock = entry(value, &path, "block", report);
+    if block.malformed.is_none() {
+        let path = format!("{}.instructions", path);
+        if let Some(instructions) = each(instructions, &path, report,
+                                         |v, p, r| entry(v, &p, "instruction", r)) {
+            block.instructions = instructions;
+        }
+    }
+
+    block
+}
+
+fn lir_block(mut value: Value, path: String, report: &mut Report) -> LirBlock {
+
+    let instructions = take_list(&mut value, "/instructions");
+
+    let mut block: LirBlock = entry(value, &path, "block", report);
+    if block.malformed.is_none() {
+        let path = format!("{}.instructions", path);
+        if let Some(instructions) = each(instructions, &path, report,
+                                         |v, p, r| entry(v, &p, "instruction", r)) {
+            block.instructions = instructions;
+        }
+    }
+
+    block
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cut_in_a_string_with_escapes() {
+        let cut = r#"{"functions": [{"name": "a\"b\\", "passes": []}, {"name": "x\"y\\z"#;
+        assert_eq!(close_truncated(cut), r#"{"functions": [{"name": "a\"b\\", "passes": []}]}"#);
+
+        // The escaped backslash does not escape the quote after it
+        let cut = r#"{"functions": [{"name": "a\\", "passes": [1, 2"#;
+        assert_eq!(close_truncated(cut), r#"{"functions": [{"name": "a\\", "passes": [1]}]}"#);
+    }
+
+    #[test]
+    fn cut_after_a_comma() {
+        assert_eq!(close_truncated(r#"{"functions": [1, 2,"#), r#"{"functions": [1, 2]}"#);
+    }
+
+    #[test]
+    fn cut_after_a_key() {
+        assert_eq!(close_truncated(r#"{"functions": [1], "name":"#), r#"{"functions": [1]}"#);
+    }
+
+    #[test]
+    fn cut_after_an_unbalanced_closer() {
+        assert_eq!(close_truncated(r#"{"functions": [1]}], {"a": [2,"#), r#"{"functions": [1]}"#);
+    }
+
+    #[test]
+    fn truncated_file() {
+        let (log, report) = from_str(r#"{"functions": [{"name": "a", "passes": []}, {"name": "b", "pa"#)
+            .unwrap();
+
+        // What there is of the second one is not a function, but is still
+        // there as a placeholder
+        assert!(report.truncated);
+        assert_eq!(log.functions.len(), 2);
+        assert_eq!(log.functions[0].name, "a");
+        assert!(log.functions[1].malformed.is_some());
+        assert_eq!(report.skipped[0].path, "functions[1]");
+    }
+
+    #[test]
+    fn malformed_instruction() {
+        let contents = r#"{"functions": [{"name": "f", "passes": [{"name": "p", "mir": {"blocks": [
+            {"number": 0, "successors": [], "instructions": [
+                {"id": 1, "opcode": "constant 1", "type": "Int32"},
+                {"id": 2, "opcode": 5, "type": "None"}
+            ]}
+        ]}}]}]}"#;
+
+        let (log, report) = from_str(contents).unwrap();
+
+        let block = &log.functions[0].passes[0].mir.blocks[0];
+        assert!(block.instructions[0].malformed.is_none());
+        assert!(block.instructions[1].malformed.is_some());
+        assert_eq!(block.instructions[1].opcode, "");
+
+        assert!(!report.truncated);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].kind, "instruction");
+        assert_eq!(report.skipped[0].path,
+                   "functions[0].passes[0].mir.blocks[0].instructions[1].opcode");
+    }
+}
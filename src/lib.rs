@@ -0,0 +1,478 @@
+//! Parse the `ion.json` file generated by SpiderMonkey's IonMonkey JIT and
+//! render it as plain text.
+//!
+//! The file is deserialized into the typed model in [`ion`], which the
+//! `parse_*` functions below walk to produce the text dump.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::iter;
+
+pub mod cfg;
+pub mod diff;
+pub mod dot;
+pub mod error;
+pub mod histogram;
+pub mod html;
+pub mod ion;
+pub mod jsonl;
+pub mod layout;
+pub mod lenient;
+pub mod select;
+pub mod stats;
+pub mod stream;
+pub mod svg;
+pub mod trace;
+
+pub use error::{Error, Result};
+pub use ion::*;
+
+/// Which of the intermediate representations to dump for every pass
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ir {
+    /// Only the MIR graph
+    Mir,
+    /// Only the LIR graph, for the passes that run after lowering
+    Lir,
+    /// MIR followed by LIR, interleaved per pass
+    Both,
+}
+
+/// How the columns of the instruction lines are lined up
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    /// Widths fitting the instructions of each block
+    Block,
+    /// Widths fitting all the instructions of each pass
+    Pass,
+    /// Widths fitting all the instructions of each function
+    Function,
+    /// The same widths everywhere, without looking at the instructions
+    Fixed,
+    /// No padding at all, one space between columns
+    None,
+}
+
+/// Widths of the columns of the instruction lines, padding included
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Widths {
+    pub opcode: usize,
+    pub operand: usize,
+
+    /// Only used with [`Options::dataflow`]
+    pub ty: usize,
+
+    /// LIR opcodes hold their operands too, so that is the only LIR column
+    pub lir_opcode: usize,
+}
+
+impl Widths {
+
+    /// Used by [`Align::Fixed`], wide enough for most of what IonMonkey
+    /// prints without wasting the whole line
+    pub const FIXED: Widths = Widths { opcode: 25, operand: 20, ty: 12, lir_opcode: 50 };
+
+    /// Widths fitting all the given instructions. This is the only place
+    /// the instructions are scanned for alignment.
+    pub fn fit<'a>(mir: impl Iterator<Item = &'a MirInstruction>,
+                   lir: impl Iterator<Item = &'a LirInstruction>) -> Widths {
+
+        let mut widths = Widths::default();
+
+        for instr in mir {
+            let (opcode, operand) = instr.opcode_and_operand();
+
+            widths.opcode  = widths.opcode.max(opcode.len() + 5);
+            widths.operand = widths.operand.max(operand.len() + 5);
+
+            // Quotes included
+            widths.ty = widths.ty.max(instr.ty.len() + 2 + 3);
+        }
+
+        for instr in lir {
+            widths.lir_opcode = widths.lir_opcode.max(instr.opcode.len() + 5);
+        }
+
+        widths
+    }
+}
+
+/// Knobs for the text dump
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    pub ir: Ir,
+
+    /// Also show the inputs, uses, memory inputs and attributes of MIR
+    /// instructions
+    pub dataflow: bool,
+
+    pub align: Align,
+
+    /// Annotate the block headers with their immediate dominator, and show
+    /// the dominator tree after the blocks of every pass
+    pub dominators: bool,
+
+    /// Show the natural loops after the blocks of every pass, checked
+    /// against the loop attributes Ion recorded
+    pub loops: bool,
+
+    /// Column widths to use as they are, whatever `align` says. Set by the
+    /// `parse_*` functions for the pass or function being dumped, or by hand
+    /// for custom fixed widths.
+    pub widths: Option<Widths>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { ir: Ir::Mir, dataflow: false, align: Align::Block, dominators: false, loops: false,
+                  widths: None }
+    }
+}
+
+impl Options {
+
+    /// The widths to line up a block with. `fit` is only called when they
+    /// have to be worked out from the block itself.
+    fn widths(&self, fit: impl FnOnce() -> Widths) -> Widths {
+        match (self.widths, self.align) {
+            (Some(widths), _) => widths,
+            (None, Align::Fixed) => Widths::FIXED,
+            (None, Align::None)  => Widths::default(),
+            (None, _) => fit(),
+        }
+    }
+
+    /// These options with the widths worked out by `fit`, if `level` is what
+    /// `align` asks for and they are not set already
+    fn fit_to(&self, level: Align, fit: impl FnOnce() -> Widths) -> Options {
+        match self.widths {
+            None if self.align == level => Options { widths: Some(fit()), ..*self },
+            _ => *self,
+        }
+    }
+
+    /// The options [`parse_function`] dumps the passes of `func` with, ie.
+    /// with the widths fitting the whole function for [`Align::Function`]
+    pub fn for_function(&self, func: &Function) -> Options {
+        self.fit_to(Align::Function, || {
+            Widths::fit(func.passes.iter().flat_map(mir_instructions),
+                        func.passes.iter().flat_map(lir_instructions))
+        })
+    }
+
+    /// The options [`parse_passes`] dumps the blocks of `pass` with, ie. with
+    /// the widths fitting the whole pass for [`Align::Pass`]. Call it on the
+    /// result of [`Options::for_function`] to get what the text dump uses.
+    pub fn for_pass(&self, pass: &Pass) -> Options {
+        self.fit_to(Align::Pass, || Widths::fit(mir_instructions(pass), lir_instructions(pass)))
+    }
+}
+
+/// What kind of output to produce
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Plain text dump of the instructions
+    Text,
+    /// Graphviz digraph of the blocks of every pass
+    Dot,
+    /// Only the MIR instructions each pass added, removed or changed
+    Diff,
+    /// Self-contained interactive HTML report
+    Html,
+    /// SVG drawing of the blocks of every pass of a single function
+    Svg,
+    /// One JSON record per instruction and line
+    Jsonl,
+}
+
+impl IonLog {
+
+    /// Read and deserialize an `ion.json` file from `reader`. `path` is only
+    /// used to tell where the file came from in errors.
+    pub fn load(reader: impl Read, path: &str) -> Result<IonLog> {
+        read(reader, path)?.parse()
+    }
+
+    /// Read and deserialize an `ion.json` file from `reader`, replacing
+    /// whatever cannot be interpreted with placeholders. See [`lenient`].
+    pub fn load_lenient(reader: impl Read, path: &str) -> Result<(IonLog, lenient::Report)> {
+        lenient::from_str(&read(reader, path)?)
+    }
+}
+
+fn read(mut reader: impl Read, path: &str) -> Result<String> {
+
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)
+          .map_err(|source| Error::Read { path: path.to_string(), source })?;
+
+    Ok(contents)
+}
+
+impl std::str::FromStr for IonLog {
+    type Err = Error;
+
+    /// Deserialize the contents of an `ion.json` file
+    fn from_str(contents: &str) -> Result<IonLog> {
+        let deserializer = &mut serde_json::Deserializer::from_str(contents);
+        serde_path_to_error::deserialize(deserializer).map_err(Error::from_json)
+    }
+}
+
+/// The `inputs: v12, v14  uses: v20` part of a MIR instruction line. Empty
+/// lists are left out.
+fn parse_dataflow(instr: &MirInstruction) -> String {
+
+    let mut debugout = String::new();
+
+    for (label, ids) in [("inputs", &instr.inputs), ("uses", &instr.uses),
+                         ("mem", &instr.mem_inputs)] {
+        if ids.is_empty() {
+            continue;
+        }
+
+        let ids = ids.iter().map(|id| format!("v{}", id)).collect::<Vec<_>>();
+        debugout += &format!("  {}: {}", label, ids.join(", "));
+    }
+
+    if !instr.attributes.is_empty() {
+        debugout += &format!("  [{}]", instr.attributes.join(", "));
+    }
+
+    debugout
+}
+
+pub fn parse_instructions(debugout: &mut impl Write,
+                          instructions: &[MirInstruction], options: &Options) -> io::Result<()> {
+
+    // Unless the widths were already worked out for the whole pass or
+    // function, this goes over the instructions once more just to find the
+    // longest opcode, operand and type. Use `Align::Fixed` or `Align::None`
+    // on large graphs to skip that.
+    let widths = options.widths(|| Widths::fit(instructions.iter(), iter::empty()));
+
+    // Now go through each instruction in this block and parse that.
+    for instr in instructions.iter() {
+        if let Some(reason) = &instr.malformed {
+            writeln!(debugout, "          ???: <malformed instruction: {}>", reason)?;
+            continue;
+        }
+
+        let (opcode, operand) = instr.opcode_and_operand();
+
+        if options.dataflow {
+            let line = format!("          {:>3}: {:<opw$} {:<orw$} {:<tyw$}{}",
+                               instr.id, opcode, operand, format!("{:?}", instr.ty),
+                               parse_dataflow(instr),
+                               opw = widths.opcode, orw = widths.operand,
+                               tyw = widths.ty);
+            writeln!(debugout, "{}", line.trim_end())?;
+        } else {
+            writeln!(debugout, "          {:>3}: {:<opw$} {:<orw$} {:?}",
+                                 instr.id, opcode, operand, instr.ty,
+                                 opw = widths.opcode, orw = widths.operand)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `Block#7 [loopheader, depth 2] preds: 3, 11`, leaving out whatever the
+/// block does not have
+pub fn parse_block_header(block: &MirBlock) -> String {
+
+    let mut debugout = format!("Block#{}", block.number);
+
+    let mut notes = block.attributes.clone();
+    if block.loop_depth > 0 {
+        notes.push(format!("depth {}", block.loop_depth));
+    }
+
+    if !notes.is_empty() {
+        debugout += &format!(" [{}]", notes.join(", "));
+    }
+
+    if !block.predecessors.is_empty() {
+        let preds = block.predecessors.iter()
+                                      .map(|p| p.to_string())
+                                      .collect::<Vec<_>>();
+        debugout += &format!(" preds: {}", preds.join(", "));
+    }
+
+    debugout
+}
+
+pub fn parse_blocks(debugout: &mut impl Write,
+                    blocks: &[MirBlock], options: &Options) -> io::Result<()> {
+
+    let idoms = match options.dominators {
+        true  => cfg::immediate_dominators(blocks),
+        false => HashMap::new(),
+    };
+
+    for block in blocks.iter() {
+        if let Some(reason) = &block.malformed {
+            writeln!(debugout, "\n      Block#?? <malformed block: {}>", reason)?;
+            continue;
+        }
+
+        match idoms.get(&block.number) {
+            Some(idom) => writeln!(debugout, "\n      {} idom: {}", parse_block_header(block), idom)?,
+            None => writeln!(debugout, "\n      {}", parse_block_header(block))?,
+        }
+
+        parse_instructions(debugout, &block.instructions, options)?;
+
+        let successors = &block.successors;
+
+        if successors.len() == 1 {
+            writeln!(debugout, "          Successor: Block#{}", successors[0])?;
+        } else if successors.len() == 2 {
+            writeln!(debugout, "          Successors: T:Block#{} F:Block#{}",
+                                 successors[0], successors[1])?;
+
+        } else if successors.len() > 2 {
+
+            let successors = successors.iter()
+                                       .map(|v| format!("Block#{}", v))
+                                       .collect::<Vec<_>>();
+            writeln!(debugout, "Successors: {}", successors.join(" "))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse_lir_instructions(debugout: &mut impl Write,
+                              instructions: &[LirInstruction], options: &Options) -> io::Result<()> {
+
+    // LIR opcodes are the whole `LNode::dump()` output, operands included, so
+    // there is only one column to align here.
+    let widths = options.widths(|| Widths::fit(iter::empty(), instructions.iter()));
+
+    for instr in instructions.iter() {
+        if let Some(reason) = &instr.malformed {
+            writeln!(debugout, "          ???: <malformed instruction: {}>", reason)?;
+            continue;
+        }
+
+        let defs = instr.defs.iter()
+                             .map(|v| format!("v{}", v))
+                             .collect::<Vec<_>>();
+
+        if defs.is_empty() {
+            writeln!(debugout, "          {:>3}: {}", instr.id, instr.opcode)?;
+        } else {
+            writeln!(debugout, "          {:>3}: {:<opw$} defs: {}",
+                                 instr.id, instr.opcode, defs.join(", "),
+                                 opw = widths.lir_opcode)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse_lir_blocks(debugout: &mut impl Write,
+                        blocks: &[LirBlock], options: &Options) -> io::Result<()> {
+
+    for block in blocks.iter() {
+        if let Some(reason) = &block.malformed {
+            writeln!(debugout, "\n      Block#?? <malformed block: {}>", reason)?;
+            continue;
+        }
+
+        writeln!(debugout, "\n      Block#{}", block.number)?;
+        parse_lir_instructions(debugout, &block.instructions, options)?;
+    }
+
+    Ok(())
+}
+
+fn mir_instructions(pass: &Pass) -> impl Iterator<Item = &MirInstruction> {
+    pass.mir.blocks.iter().flat_map(|b| b.instructions.iter())
+}
+
+fn lir_instructions(pass: &Pass) -> impl Iterator<Item = &LirInstruction> {
+    pass.lir.iter().flat_map(|l| l.blocks.iter()).flat_map(|b| b.instructions.iter())
+}
+
+pub fn parse_passes(debugout: &mut impl Write,
+                    passes: &[Pass], options: &Options) -> io::Result<()> {
+
+    let ir = options.ir;
+
+    for pass in passes.iter() {
+
+        if let Some(reason) = &pass.malformed {
+            writeln!(debugout, "\n\n  After Ion Phase <malformed pass: {}>\n", reason)?;
+            continue;
+        }
+
+        // Passes that run before lowering have no `lir` section at all, so
+        // there is nothing to show for them when only LIR was asked for.
+        if ir == Ir::Lir && pass.lir.is_none() {
+            continue;
+        }
+
+        writeln!(debugout, "\n\n  After Ion Phase {:?}\n", pass.name)?;
+
+        let options = options.for_pass(pass);
+
+        // Fetch the basic blocks in this pass and parse them.
+        if ir != Ir::Lir {
+            if ir == Ir::Both {
+                writeln!(debugout, "    MIR")?;
+            }
+
+            parse_blocks(debugout, &pass.mir.blocks, &options)?;
+
+            if options.dominators {
+                cfg::parse_dominator_tree(debugout, &pass.mir.blocks)?;
+            }
+
+            if options.loops {
+                cfg::parse_loops(debugout, &pass.mir.blocks)?;
+            }
+        }
+
+        if ir != Ir::Mir {
+            if let Some(lir) = &pass.lir {
+                if ir == Ir::Both {
+                    writeln!(debugout, "\n    LIR")?;
+                }
+
+                parse_lir_blocks(debugout, &lir.blocks, &options)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse_function(debugout: &mut impl Write,
+                      func: &Function, options: &Options) -> io::Result<()> {
+
+    if let Some(reason) = &func.malformed {
+        return write!(debugout, "\n\nGraph for Function: <malformed function: {}>", reason);
+    }
+
+    write!(debugout, "\n\nGraph for Function: {:?}", func.name)?;
+
+    let options = options.for_function(func);
+
+    // Parse the optimization passes that ran on this function
+    parse_passes(debugout, &func.passes, &options)?;
+
+    Ok(())
+}
+
+pub fn parse_graph(debugout: &mut impl Write,
+                   iondata: &IonLog, options: &Options) -> io::Result<()> {
+
+    // Go through all the functions that were ion compiled
+    for func in iondata.functions.iter() {
+        parse_function(debugout, func, options)?;
+    }
+
+    Ok(())
+}
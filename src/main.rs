@@ -1,171 +1,496 @@
-use serde_json::Value;
-use clap::Parser;
-
-macro_rules! unwrap {
-    ($result: expr, $message: expr) => {
-        match $result {
-            Ok(res)  => res,
-            Err(err) => {
-                println!("[-] {}: {}", $message, err);
-                std::process::exit(-1);
-            }
-        }
-    };
+use clap::{Parser, Subcommand};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use iongraph::{diff, dot, histogram, html, jsonl, lenient, parse_function, select, stats, stream, svg, trace, Align, Error, Format,
+               Function, Ir, IonLog, Options};
+use iongraph::histogram::{Histogram, Sort};
+use iongraph::select::{PassSelector, Selector};
+
+#[cfg(feature = "tui")]
+mod tui;
+
+/// Simple script to convert the ion.json file into a text based IR form
+#[derive(Parser, Debug)]
+#[clap(author, about, long_about=None)]
+struct Args {
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Path of the ion.json file, `-` for stdin [default: /tmp/ion.json, or
+    /// stdin when something is piped into it]
+    #[clap(short, long, value_parser, global = true)]
+    ionfile: Option<String>,
+
+    /// Path of the file where to save the output, `-` for stdout [default:
+    /// /tmp/iongraph, or stdout when reading from stdin]
+    #[clap(short, long, value_parser, global = true)]
+    outfile: Option<String>,
+
+    /// Intermediate representation to dump
+    #[clap(long, value_enum, default_value = "mir")]
+    ir: Ir,
+
+    /// Show the inputs, uses, memory inputs and attributes of every MIR
+    /// instruction
+    #[clap(short, long)]
+    dataflow: bool,
+
+    /// How to line up the columns of the instructions. `block` looks at
+    /// every block twice, use `pass`, `function`, `fixed` or `none` on large
+    /// logs
+    #[clap(long, value_enum, default_value = "block")]
+    align: Align,
+
+    /// Show the immediate dominator of every MIR block in its header, and
+    /// the dominator tree after the blocks of every pass
+    #[clap(long)]
+    dominators: bool,
+
+    /// Show the natural loops of every pass, with their body, backedges and
+    /// exits, and where they disagree with the loop attributes in the JSON
+    #[clap(long)]
+    loops: bool,
+
+    /// Output format
+    #[clap(long, value_enum, default_value = "text")]
+    format: Format,
+
+    /// Only dump the functions with this index, name, or whose name matches
+    /// this regex
+    #[clap(short, long, global = true)]
+    function: Option<String>,
+
+    /// Just list the index, name and number of passes of every function
+    #[clap(short, long)]
+    list: bool,
+
+    /// Only dump the passes with this index, name, or whose name matches
+    /// this regex. `START..END` selects a range, eg. "Apply types..GVN"
+    #[clap(short, long, global = true, conflicts_with_all = &["first", "last"])]
+    pass: Option<String>,
+
+    /// Only dump the first pass of every function
+    #[clap(long, global = true, conflicts_with = "last")]
+    first: bool,
+
+    /// Only dump the last pass of every function
+    #[clap(long, global = true)]
+    last: bool,
+
+    /// Number of threads rendering functions in parallel [default: number of
+    /// CPUs]
+    #[clap(short, long)]
+    jobs: Option<usize>,
+
+    /// Follow the MIR instruction with this id through every pass of the
+    /// selected functions, one line per pass
+    #[clap(short, long, requires = "function")]
+    trace: Option<u32>,
+
+    /// Replace functions, passes, blocks and instructions that cannot be
+    /// interpreted with placeholders instead of giving up
+    #[clap(long, global = true)]
+    lenient: bool,
+
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+
+    /// Compare the ion.json files of two engine builds, and show the passes
+    /// where their compilations differ
+    Compare {
+        /// ion.json file of the known good build, `-` for stdin
+        old: String,
+
+        /// ion.json file of the build to compare against it, `-` for stdin
+        new: String,
+    },
+
+    /// Browse the functions, passes and blocks in the terminal
+    #[cfg(feature = "tui")]
+    Tui,
+
+    /// Count the blocks, instructions, phis, loop headers and distinct
+    /// opcodes after every pass, and how much each pass changed them
+    Stats,
+
+    /// How often each MIR opcode shows up across all the functions, after
+    /// the last pass unless `--pass`, `--first` or `--last` say otherwise
+    Opcodes {
+        /// Order of the opcodes
+        #[clap(long, value_enum, default_value = "count")]
+        sort: Sort,
+
+        /// Also show the histogram of each function on its own
+        #[clap(long)]
+        by_function: bool,
+    },
 }
 
-fn deserialize_json(filename: String) -> Value {
 
-    let contents = unwrap!(
-        std::fs::read_to_string(filename),
-        "Not able to read json file");
+/// Whether something is piped or redirected into stdin, as opposed to it
+/// being a terminal or `/dev/null` like under most CI harnesses
+#[cfg(unix)]
+fn piped_stdin() -> bool {
+    use std::os::fd::AsFd;
+    use std::os::unix::fs::FileTypeExt;
 
-    let data = unwrap!(
-        serde_json::from_str(&contents),
-        "Not able to parse ion.json");
+    let metadata = io::stdin().as_fd()
+                              .try_clone_to_owned()
+                              .and_then(|fd| File::from(fd).metadata());
 
-    data
+    match metadata {
+        Ok(metadata) => metadata.file_type().is_fifo() || metadata.is_file(),
+        Err(_) => false,
+    }
 }
 
-fn parse_instructions(instructions: &Vec<Value>) -> Option<String> {
-
-    let mut debugout = String::new();
-
-    // First iteration as a dumb way to find the length of the longest opcode
-    // and operand. This will be used to align the output properly. Note that if
-    // a graph is large, then this will slow it down, so in that case, just give
-    // `opcode_len` and `operand_len` a large value and comment this out.
-    let mut opcode_len  = 0;
-    let mut operand_len = 0;
-    for instr in instructions.into_iter() {
-        let instruction = instr["opcode"].as_str()?;
-        let (opcode, operand) = match instruction.split_once(" ") {
-            Some((opcode, operand)) =>  (opcode, operand),
-            None => (instruction, "")
-        };
+#[cfg(not(unix))]
+fn piped_stdin() -> bool {
+    use std::io::IsTerminal;
 
-        if opcode.len() > opcode_len {
-            opcode_len = opcode.len();
-        }
+    !io::stdin().is_terminal()
+}
 
-        if operand.len() > operand_len {
-            operand_len = operand.len();
-        }
+/// Open the input for buffered reading, `-` being stdin
+fn input(path: &str) -> iongraph::Result<Box<dyn Read>> {
+
+    if path == "-" {
+        return Ok(Box::new(BufReader::new(io::stdin().lock())));
     }
 
-    // Now go through each instruction in this block and parse that.
-    for instr in instructions.into_iter() {
-        let id     = instr["id"].as_u64()?;
-        let instruction = instr["opcode"].as_str()?;
-        let (opcode, operand) = match instruction.split_once(" ") {
-            Some((opcode, operand)) =>  (opcode, operand),
-            None => (instruction, "")
-        };
+    let file = File::open(path)
+        .map_err(|source| Error::Read { path: path.to_string(), source })?;
 
-        debugout += &format!("          {:>3}: {:<opw$} {:<orw$} {}\n",
-                             id, opcode, operand, instr["type"],
-                             opw = opcode_len + 5, orw = operand_len + 5);
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// Tell what a lenient load had to skip
+fn warn(report: &lenient::Report) {
+
+    for diagnostic in report.skipped.iter() {
+        eprintln!("[!] {}", diagnostic);
     }
 
-    Some(debugout)
+    if report.truncated || !report.skipped.is_empty() {
+        eprintln!("[!] {}", report.summary());
+    }
 }
 
-fn parse_blocks(blocks: &Vec<Value>) -> Option<String> {
+fn load(filename: &str, args: &Args) -> iongraph::Result<IonLog> {
 
-    let mut debugout = String::new();
+    let input = input(filename)?;
 
-    for block in blocks.into_iter() {
-        debugout += &format!("\n      Block#{}\n", block["number"]);
+    if !args.lenient {
+        return IonLog::load(input, filename);
+    }
 
-        let instructions = block["instructions"].as_array()?;
-        debugout += &parse_instructions(instructions)?;
+    let (iondata, report) = IonLog::load_lenient(input, filename)?;
+    warn(&report);
 
-        let successors = block["successors"].as_array()?;
+    Ok(iondata)
+}
 
-        if successors.len() == 1 {
-            debugout += &format!("          Successor: Block#{}\n", successors[0]);
-        } else if successors.len() == 2 {
-            debugout += &format!("          Successors: T:Block#{} F:Block#{}\n",
-                                 successors[0], successors[1]);
+/// The `--function` and `--pass` selections, if any
+fn selectors(args: &Args) -> iongraph::Result<(Option<Selector>, Option<PassSelector>)> {
 
-        } else if successors.len() > 2 {
+    let functions = args.function.as_deref().map(Selector::parse);
 
-            let successors = successors.into_iter()
-                                       .map(|v| format!("Block#{}", v))
-                                       .collect::<Vec<_>>();
-            debugout += &format!("Successors: {}\n", successors.join(" "));
-        }
-    }
+    let passes = match &args.pass {
+        Some(spec) => Some(PassSelector::parse(spec)?),
+        None if args.first => Some(PassSelector::First),
+        None if args.last  => Some(PassSelector::Last),
+        None => None,
+    };
 
-    Some(debugout)
+    Ok((functions, passes))
 }
 
-fn parse_passes(passes: &Vec<Value>) -> Option<String> {
+/// Narrow a whole log down to the functions and passes that were asked for
+fn select(iondata: &mut IonLog, args: &Args) -> iongraph::Result<()> {
 
-    let mut debugout = String::new();
+    let (functions, passes) = selectors(args)?;
 
-    for pass in passes.into_iter() {
-        debugout += &format!("\n\n  After Ion Phase {}\n\n", pass["name"]);
+    if let Some(functions) = &functions {
+        select::select_functions(iondata, functions)?;
+    }
 
-        // Fetch the basic blocks in this pass and parse them. We are only
-        // looking at MIR code now.
-        // TODO: Add support for LIR as well
-        let mirblocks = pass["mir"]["blocks"].as_array()?;
-        debugout += &parse_blocks(mirblocks)?;
+    if let Some(passes) = &passes {
+        select::select_passes(iondata, passes)?;
     }
 
-    Some(debugout)
+    Ok(())
 }
 
-fn parse_graph(iondata: Value) -> Option<String> {
+/// Open the output for buffered writing, `-` being stdout
+fn output(path: &str) -> iongraph::Result<Box<dyn Write>> {
 
-    // This will hold the output disassembly
-    let mut debugout = String::new();
+    if path == "-" {
+        return Ok(Box::new(BufWriter::new(io::stdout().lock())));
+    }
+
+    let file = File::create(path)
+        .map_err(|source| Error::Write { path: path.to_string(), source })?;
 
-    // Go through all the functions that were ion compiled
-    for func in iondata["functions"].as_array()?.into_iter() {
-        debugout += &format!("\n\nGraph for Function: {}", func["name"]);
+    Ok(Box::new(BufWriter::new(file)))
+}
 
-        // Fetch the optimization passes that ran on this function and parse
-        // them
-        let passes = func["passes"].as_array()?;
-        debugout += &parse_passes(passes)?;
+/// Render one function the way it was asked for
+fn render(debugout: &mut impl Write, index: usize, func: &Function, args: &Args,
+          options: &Options) -> io::Result<()> {
+
+    // The listing wins over everything else, as it does for `opcodes`
+    if args.list {
+        return debugout.write_all(select::list_function(index, func).as_bytes());
+    }
+
+    if let Some(Command::Stats) = &args.command {
+        return stats::parse_function(debugout, func);
     }
 
-    Some(debugout)
+    if let Some(id) = args.trace {
+        return trace::parse_function(debugout, func, id);
+    }
+
+    match args.format {
+        Format::Text => parse_function(debugout, func, options),
+        Format::Dot  => dot::parse_function(debugout, func, args.ir),
+        Format::Diff => diff::parse_function(debugout, func),
+        Format::Html => html::parse_function(debugout, index, func, options),
+        Format::Svg  => svg::parse_function(debugout, func, args.ir),
+        Format::Jsonl => jsonl::parse_function(debugout, index, func, args.ir),
+    }
 }
 
-/// Simple script to convert the ion.json file into a text based IR form
-#[derive(Parser, Debug)]
-#[clap(author, about, long_about=None)]
-struct Args {
+/// Render a batch of functions on the thread pool, and write them out in the
+/// order they were read
+fn render_batch(debugout: &mut impl Write, pool: &ThreadPool, batch: &mut Vec<(usize, Function)>,
+                args: &Args, options: &Options) -> io::Result<()> {
 
-    /// Path of the ion.json file
-    #[clap(short, long, value_parser, default_value = "/tmp/ion.json")]
-    ionfile: String,
+    let rendered = pool.install(|| {
+        batch.par_iter()
+             .map(|(index, func)| {
+                 let mut buffer = Vec::new();
+                 render(&mut buffer, *index, func, args, options).map(|_| buffer)
+             })
+             .collect::<io::Result<Vec<_>>>()
+    })?;
 
-    /// Path of the file where to save the output
-    #[clap(short, long, value_parser, default_value = "/tmp/iongraph")]
-    outfile: String,
+    batch.clear();
 
+    for buffer in rendered.iter() {
+        debugout.write_all(buffer)?;
+    }
+
+    Ok(())
 }
 
+fn run(args: Args) -> iongraph::Result<()> {
+
+    if let Some(Command::Compare { old: old_path, new: new_path }) = &args.command {
+        let mut old = load(old_path, &args)?;
+        let mut new = load(new_path, &args)?;
+
+        select(&mut old, &args)?;
+        select(&mut new, &args)?;
+
+        let outfile = match &args.outfile {
+            Some(outfile) => outfile.as_str(),
+            None if old_path == "-" || new_path == "-" => "-",
+            None => "/tmp/iongraph",
+        };
+        let mut output = output(outfile)?;
+        return diff::compare(&mut output, &old, &new)
+            .and_then(|_| output.flush())
+            .map_err(|source| Error::Write { path: outfile.to_string(), source });
+    }
+
+    let ionfile = match &args.ionfile {
+        Some(ionfile) => ionfile.as_str(),
+        None if piped_stdin() => "-",
+        None => "/tmp/ion.json",
+    };
+
+    // Reading from a pipe means we are in the middle of a pipeline, so keep
+    // the output flowing down it
+    let outfile = match &args.outfile {
+        Some(outfile) => outfile.as_str(),
+        None if ionfile == "-" => "-",
+        None => "/tmp/iongraph",
+    };
+
+    let options = Options {
+        ir: args.ir,
+        dataflow: args.dataflow,
+        align: args.align,
+        dominators: args.dominators,
+        loops: args.loops,
+        ..Default::default()
+    };
+
+    #[cfg(feature = "tui")]
+    if let Some(Command::Tui) = &args.command {
+        let mut iondata = load(ionfile, &args)?;
+        select(&mut iondata, &args)?;
+        return tui::run(iondata, options);
+    }
+
+    let (functions, mut passes) = selectors(&args)?;
+
+    let mut histogram = match &args.command {
+        Some(Command::Opcodes { by_function, .. }) if !args.list => {
+            passes = passes.or(Some(PassSelector::Last));
+            Some(Histogram::new(*by_function))
+        }
+        _ => None,
+    };
+
+    let input = input(ionfile)?;
+
+    // The listing is short, so it goes to the terminal unless asked otherwise
+    let outpath = match &args.outfile {
+        None if args.list => "-",
+        _ => outfile,
+    };
+    let mut output = output(outpath)?;
+
+    let html = args.format == Format::Html && !args.list && args.trace.is_none() &&
+               args.command.is_none();
+    // An SVG document holds a single function, so the one selected is only
+    // drawn once it is certain no other one follows
+    let svg = args.format == Format::Svg && !args.list && args.trace.is_none() &&
+              args.command.is_none();
+    let mut drawing: Option<(usize, Function)> = None;
+
+    if html {
+        let title = if ionfile == "-" { "iongraph" } else { ionfile };
+        html::parse_header(&mut output, title)
+            .map_err(|source| Error::Write { path: outpath.to_string(), source })?;
+    }
+
+    // With a single job everything is rendered straight into the output,
+    // otherwise a few functions per thread are read ahead and rendered in
+    // parallel
+    let pool = match args.jobs {
+        Some(1) => None,
+        jobs => Some(ThreadPoolBuilder::new().num_threads(jobs.unwrap_or(0))
+                                             .build()
+                                             .map_err(Error::Threads)?),
+    };
+    let batch_size = pool.as_ref().map_or(0, |pool| pool.current_num_threads() * 4);
+    let mut batch  = Vec::with_capacity(batch_size);
+
+    let mut index   = 0;
+    let mut matched = 0;
+    let mut dumped  = 0;
+
+    // The ion.json file is walked one function at a time, and each of them is
+    // written out as soon as it has been read
+    let visit = |mut func: Function| {
+        index += 1;
+
+        if matches!(&functions, Some(s) if !s.matches(index - 1, &func.name)) {
+            return Ok(());
+        }
+        matched += 1;
+
+        if let Some(passes) = passes.as_ref().filter(|_| !args.list) {
+            passes.retain(&mut func.passes);
+            if func.passes.is_empty() {
+                return Ok(());
+            }
+        }
+        dumped += 1;
+
+        // Only counted for now, the histogram is written once all of them
+        // have been seen
+        if let Some(histogram) = &mut histogram {
+            histogram.add(index - 1, &func);
+            return Ok(());
+        }
+
+        if svg {
+            if let Some((_, first)) = &drawing {
+                return Err(Error::Select(format!(
+                    "--format svg draws a single function, but both {:?} and {:?} were selected, \
+                     pick one with --function", first.name, func.name)));
+            }
+            drawing = Some((index - 1, func));
+            return Ok(());
+        }
+
+        let written = match &pool {
+            None => render(&mut output, index - 1, &func, &args, &options),
+            Some(pool) => {
+                batch.push((index - 1, func));
+                match batch.len() < batch_size {
+                    true  => Ok(()),
+                    false => render_batch(&mut output, pool, &mut batch, &args, &options),
+                }
+            }
+        };
+
+        written.map_err(|source| Error::Write { path: outpath.to_string(), source })
+    };
+
+    if args.lenient {
+        warn(&stream::for_each_function_lenient(input, visit)?);
+    } else {
+        stream::for_each_function(input, visit)?;
+    }
+
+    if let Some(pool) = &pool {
+        render_batch(&mut output, pool, &mut batch, &args, &options)
+            .map_err(|source| Error::Write { path: outpath.to_string(), source })?;
+    }
+
+    if let Some((index, func)) = &drawing {
+        render(&mut output, *index, func, &args, &options)
+            .map_err(|source| Error::Write { path: outpath.to_string(), source })?;
+    }
+
+    let counted = histogram.as_ref().filter(|h| h.functions > 0);
+    if let (Some(histogram), Some(Command::Opcodes { sort, .. })) = (counted, &args.command) {
+        histogram::parse_histogram(&mut output, histogram, *sort)
+            .map_err(|source| Error::Write { path: outpath.to_string(), source })?;
+    }
+
+    if html {
+        html::parse_footer(&mut output)
+            .map_err(|source| Error::Write { path: outpath.to_string(), source })?;
+    }
+
+    output.flush()
+          .map_err(|source| Error::Write { path: outpath.to_string(), source })?;
+
+    match (&functions, &passes) {
+        (Some(functions), _) if matched == 0 => Err(Error::Select(format!(
+            "No function matches {}, out of the {} that were compiled", functions, index))),
+        (_, Some(passes)) if dumped == 0 => Err(Error::Select(format!(
+            "No pass matches {}", passes))),
+        _ => Ok(()),
+    }
+}
 
 fn main() {
 
     let args = Args::parse();
 
-    // Parse the ion.json file into the program
-    let iondata = deserialize_json(args.ionfile);
+    match run(args) {
+        Ok(()) => {}
 
-    let debugout = if let Some(output) = parse_graph(iondata) {
-        output
-    } else {
-        println!("[-] Invalid ion logs json file encountered");
-        std::process::exit(-1);
-    };
+        // Whatever was reading our output, eg. `head`, has seen enough
+        Err(Error::Write { source, .. }) if source.kind() == io::ErrorKind::BrokenPipe => {}
 
-    let _ = unwrap!(
-        std::fs::write(args.outfile, debugout),
-        "unable to write output");
+        Err(err) => {
+            eprintln!("[-] {}", err);
+            std::process::exit(err.exit_code());
+        }
+    }
 
 }
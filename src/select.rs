@@ -0,0 +1,194 @@
+//! Picking the functions and passes to look at, as given on the command line.
+
+use std::fmt;
+
+use regex::Regex;
+
+use crate::{Error, Function, IonLog, Pass, Result};
+
+/// One way of naming an entry: by its index, by its exact name, or by a
+/// regex that has to match the whole name
+#[derive(Debug, Clone)]
+pub enum Selector {
+    Index(usize),
+    Name(String),
+
+    /// The spec as given, and the anchored regex built from it
+    Pattern(String, Regex),
+}
+
+impl Selector {
+
+    /// Interpret `spec`. A number is an index, and anything else is matched
+    /// against the names both as is and as a regex, if it is a valid one.
+    ///
+    /// The regex has to match the whole name so that `test.js:1` does not
+    /// pick up `test.js:12` too. This works without knowing the names up
+    /// front, which streaming needs.
+    pub fn parse(spec: &str) -> Selector {
+
+        if let Ok(index) = spec.parse() {
+            return Selector::Index(index);
+        }
+
+        match Regex::new(&format!("^(?:{})$", spec)) {
+            Ok(regex) => Selector::Pattern(spec.to_string(), regex),
+            Err(_) => Selector::Name(spec.to_string()),
+        }
+    }
+
+    pub fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            Selector::Index(idx)           => *idx == index,
+            Selector::Name(exact)          => exact == name,
+            Selector::Pattern(spec, regex) => spec == name || regex.is_match(name),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Index(idx)      => write!(f, "#{}", idx),
+            Selector::Name(name)      => write!(f, "{:?}", name),
+            Selector::Pattern(spec, _) => write!(f, "/{}/", spec),
+        }
+    }
+}
+
+/// Drop every function that `selector` does not match. Selecting nothing at
+/// all is an error, since that is never what was meant.
+pub fn select_functions(iondata: &mut IonLog, selector: &Selector) -> Result<()> {
+
+    let compiled = iondata.functions.len();
+
+    let mut index = 0;
+    iondata.functions.retain(|func| {
+        index += 1;
+        selector.matches(index - 1, &func.name)
+    });
+
+    if iondata.functions.is_empty() {
+        return Err(Error::Select(format!(
+            "No function matches {}, out of the {} that were compiled", selector, compiled)));
+    }
+
+    Ok(())
+}
+
+/// The `--list` line for a function: its index, name and number of passes
+pub fn list_function(index: usize, func: &Function) -> String {
+    format!("{:>5}: {}  ({} passes)\n", index, func.name, func.passes.len())
+}
+
+/// Which passes of each function to keep
+#[derive(Debug, Clone)]
+pub enum PassSelector {
+    One(Selector),
+
+    /// Inclusive range, from the first pass matching the start to the first
+    /// one after it matching the end. A missing end means the first or the
+    /// last pass.
+    Range(Option<Selector>, Option<Selector>),
+
+    First,
+    Last,
+}
+
+impl PassSelector {
+
+    /// Interpret the `--pass` argument. `A..B` is a range, and each end is
+    /// an index, a name or a regex just like a single pass.
+    pub fn parse(spec: &str) -> Result<PassSelector> {
+
+        let endpoint = |spec: &str| match spec.is_empty() {
+            true  => None,
+            false => Some(Selector::parse(spec)),
+        };
+
+        match spec.split_once("..") {
+            Some((start, end)) => Ok(PassSelector::Range(endpoint(start), endpoint(end))),
+            None => match endpoint(spec) {
+                Some(selector) => Ok(PassSelector::One(selector)),
+                None => Err(Error::Select("An empty pass selection was given".to_string())),
+            },
+        }
+    }
+
+    /// Keep only the selected passes out of those of one function
+    pub fn retain(&self, passes: &mut Vec<Pass>) {
+        match self {
+            PassSelector::One(selector) => {
+                let mut index = 0;
+                passes.retain(|pass| {
+                    index += 1;
+                    selector.matches(index - 1, &pass.name)
+                });
+            }
+            PassSelector::First => passes.truncate(1),
+            PassSelector::Last => {
+                let skip = passes.len().saturating_sub(1);
+                passes.drain(..skip);
+            }
+            PassSelector::Range(start, end) => {
+                let position = |selector: &Selector, from: usize| {
+                    passes.iter()
+                          .enumerate()
+                          .skip(from)
+                          .position(|(idx, pass)| selector.matches(idx, &pass.name))
+                          .map(|pos| pos + from)
+                };
+
+                let first = match start {
+                    Some(start) => position(start, 0),
+                    None => Some(0),
+                };
+
+                let range = first.and_then(|first| {
+                    match end {
+                        Some(end) => position(end, first).map(|last| first..last + 1),
+                        None => Some(first..passes.len()),
+                    }
+                });
+
+                *passes = match range {
+                    Some(range) => passes.drain(range).collect(),
+                    None => Vec::new(),
+                };
+            }
+        }
+    }
+}
+
+impl fmt::Display for PassSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let endpoint = |selector: &Option<Selector>| match selector {
+            Some(selector) => selector.to_string(),
+            None => String::new(),
+        };
+
+        match self {
+            PassSelector::One(selector) => write!(f, "{}", selector),
+            PassSelector::Range(start, end) => write!(f, "{}..{}", endpoint(start), endpoint(end)),
+            PassSelector::First => write!(f, "the first pass"),
+            PassSelector::Last  => write!(f, "the last pass"),
+        }
+    }
+}
+
+/// Drop every pass that `selector` does not match, and the functions that
+/// are left without any
+pub fn select_passes(iondata: &mut IonLog, selector: &PassSelector) -> Result<()> {
+
+    for func in iondata.functions.iter_mut() {
+        selector.retain(&mut func.passes);
+    }
+
+    iondata.functions.retain(|func| !func.passes.is_empty());
+
+    if iondata.functions.is_empty() {
+        return Err(Error::Select(format!("No pass matches {}", selector)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Per-pass statistics of the MIR graph, to see at a glance which passes
+//! actually shrink it.
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::{Function, IonLog, Pass};
+
+/// Size of the MIR graph after one pass. Malformed blocks and instructions
+/// are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PassStats {
+    pub blocks: usize,
+    pub instructions: usize,
+    pub phis: usize,
+    pub loop_headers: usize,
+
+    /// Number of distinct opcodes
+    pub opcodes: usize,
+}
+
+impl PassStats {
+
+    pub fn of(pass: &Pass) -> PassStats {
+
+        let mut stats   = PassStats::default();
+        let mut opcodes = HashSet::new();
+
+        for block in pass.mir.blocks.iter().filter(|b| b.malformed.is_none()) {
+            stats.blocks += 1;
+            if block.has_attribute("loopheader") {
+                stats.loop_headers += 1;
+            }
+
+            for instr in block.instructions.iter().filter(|i| i.malformed.is_none()) {
+                let (opcode, _) = instr.opcode_and_operand();
+
+                stats.instructions += 1;
+                if opcode.eq_ignore_ascii_case("phi") {
+                    stats.phis += 1;
+                }
+                opcodes.insert(opcode);
+            }
+        }
+
+        stats.opcodes = opcodes.len();
+        stats
+    }
+
+    fn counts(&self) -> [usize; 5] {
+        [self.blocks, self.instructions, self.phis, self.loop_headers, self.opcodes]
+    }
+}
+
+const COLUMNS: [&str; 5] = ["Blocks", "Instrs", "Phis", "Loops", "Opcodes"];
+
+/// A count with its change since the previous pass, eg. `12 (-3)`
+fn cell(count: usize, previous: Option<usize>) -> String {
+    match previous {
+        Some(previous) if previous != count => {
+            format!("{} ({:+})", count, count as i64 - previous as i64)
+        }
+        _ => count.to_string(),
+    }
+}
+
+/// A table of the statistics of every pass of a function, with the change
+/// since the previous pass next to each count
+pub fn parse_function(debugout: &mut impl Write, func: &Function) -> io::Result<()> {
+
+    if func.malformed.is_some() {
+        return Ok(());
+    }
+
+    writeln!(debugout, "\n\nStatistics for Function: {:?}\n", func.name)?;
+
+    let name_len = func.passes.iter()
+                              .map(|p| p.name.len())
+                              .max()
+                              .unwrap_or(0);
+
+    let mut header = format!("  {:<nw$}", "Pass", nw = name_len);
+    for column in COLUMNS.iter() {
+        header += &format!("  {:<12}", column);
+    }
+    writeln!(debugout, "{}", header.trim_end())?;
+
+    let mut previous: Option<PassStats> = None;
+
+    for pass in func.passes.iter() {
+        if pass.malformed.is_some() {
+            writeln!(debugout, "  <malformed pass>")?;
+            previous = None;
+            continue;
+        }
+
+        let stats = PassStats::of(pass);
+
+        let mut line = format!("  {:<nw$}", pass.name, nw = name_len);
+        for (idx, count) in stats.counts().iter().enumerate() {
+            line += &format!("  {:<12}", cell(*count, previous.map(|p| p.counts()[idx])));
+        }
+        writeln!(debugout, "{}", line.trim_end())?;
+
+        previous = Some(stats);
+    }
+
+    Ok(())
+}
+
+pub fn parse_graph(debugout: &mut impl Write, iondata: &IonLog) -> io::Result<()> {
+
+    for func in iondata.functions.iter() {
+        parse_function(debugout, func)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,302 @@
+//! Streaming deserialization of `ion.json` files.
+//!
+//! Logs from long benchmark runs are several gigabytes, so instead of
+//! building the whole [`IonLog`](crate::IonLog) this hands the functions over
+//! one at a time, as soon as each of them has been read. Memory use is bounded
+//! by the largest single function rather than by the size of the file.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+
+use crate::lenient::{self, Report};
+use crate::{Error, Function, Result};
+
+struct State<F> {
+    visit: F,
+
+    /// Set when loading leniently
+    report: Option<Report>,
+
+    /// What was read of the function being deserialized, when loading
+    /// leniently, and its index
+    recorded: Option<Rc<RefCell<Vec<u8>>>>,
+    current: usize,
+
+    /// Error returned by `visit`, which stops the whole thing
+    failed: Option<Error>,
+}
+
+/// Keeps a copy of everything read through it, for as long as `recorded`
+/// is not cleared
+struct Recorder<R> {
+    inner: R,
+    recorded: Rc<RefCell<Vec<u8>>>,
+}
+
+impl<R: Read> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.recorded.borrow_mut().extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// The top level object, of which only `functions` is of interest
+struct LogSeed<'a, F>(&'a mut State<F>);
+
+/// The `functions` list
+struct FunctionsSeed<'a, F>(&'a mut State<F>);
+
+impl<'de, F> DeserializeSeed<'de> for LogSeed<'_, F>
+    where F: FnMut(Function) -> Result<()> {
+
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for LogSeed<'_, F>
+    where F: FnMut(Function) -> Result<()> {
+
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an ion.json object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<(), A::Error> {
+
+        let mut seen = false;
+
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "functions" {
+                map.next_value_seed(FunctionsSeed(&mut *self.0))?;
+                seen = true;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+
+        if !seen {
+            return Err(de::Error::missing_field("functions"));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de, F> DeserializeSeed<'de> for FunctionsSeed<'_, F>
+    where F: FnMut(Function) -> Result<()> {
+
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for FunctionsSeed<'_, F>
+    where F: FnMut(Function) -> Result<()> {
+
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of functions")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+
+        let state = self.0;
+
+        for index in 0.. {
+            // Only the function about to be read is kept, in case the file
+            // ends in the middle of it
+            state.current = index;
+            if let Some(recorded) = &state.recorded {
+                recorded.borrow_mut().clear();
+            }
+
+            let func = match &mut state.report {
+                None => match seq.next_element::<Function>()? {
+                    Some(func) => func,
+                    None => break,
+                },
+                Some(report) => match seq.next_element::<Value>()? {
+                    Some(value) => lenient::function(value, format!("functions[{}]", index), report),
+                    None => break,
+                },
+            };
+
+            if let Err(err) = (state.visit)(func) {
+                state.failed = Some(err);
+                return Err(de::Error::custom("stopped"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Walk the whole document, keeping track of where a failure happened
+fn walk<R, F>(reader: R, state: &mut State<F>) -> std::result::Result<(), serde_path_to_error::Error<serde_json::Error>>
+    where R: Read, F: FnMut(Function) -> Result<()> {
+
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let mut track = serde_path_to_error::Track::new();
+
+    LogSeed(state).deserialize(serde_path_to_error::Deserializer::new(&mut deserializer, &mut track))
+                  .map_err(|err| serde_path_to_error::Error::new(track.path(), err))
+}
+
+fn stream<R, F>(reader: R, report: Option<Report>, visit: F) -> Result<Option<Report>>
+    where R: Read, F: FnMut(Function) -> Result<()> {
+
+    // Only a lenient load has any use for what was read
+    let recorded = report.as_ref().map(|_| Rc::new(RefCell::new(Vec::new())));
+
+    let mut state = State { visit, report, recorded, current: 0, failed: None };
+
+    let result = match &state.recorded {
+        Some(recorded) => {
+            let recorder = Recorder { inner: reader, recorded: recorded.clone() };
+            walk(recorder, &mut state)
+        }
+        None => walk(reader, &mut state),
+    };
+
+    if let Some(err) = state.failed {
+        return Err(err);
+    }
+
+    match result {
+        Ok(()) => Ok(state.report),
+
+        // Everything up to the last complete function has been handed over
+        // already, so a lenient load only has the one it was in the middle
+        // of left to close up
+        Err(err) if err.inner().is_eof() && state.report.is_some() => {
+            let mut report = state.report.unwrap();
+            report.truncated = true;
+
+            let recorded = state.recorded.map(|r| r.take()).unwrap_or_default();
+            if let Some(value) = truncated_function(&recorded) {
+                let path = format!("functions[{}]", state.current);
+                (state.visit)(lenient::function(value, path, &mut report))?;
+            }
+
+            Ok(Some(report))
+        }
+
+        Err(err) => Err(Error::from_json(err)),
+    }
+}
+
+/// Close up what was read of the function the file ends in. That is
+/// whatever follows the previous function, and its comma.
+fn truncated_function(recorded: &[u8]) -> Option<Value> {
+
+    let contents = String::from_utf8_lossy(recorded);
+    let contents = contents.trim_start();
+    let contents = contents.strip_prefix(',').unwrap_or(contents).trim_start();
+
+    if !contents.starts_with('{') {
+        return None;
+    }
+
+    serde_json::from_str(&lenient::close_truncated(contents)).ok()
+}
+
+/// Deserialize an `ion.json` file from `reader`, calling `visit` on every
+/// function as soon as it has been read. An error from `visit` stops the
+/// deserialization and is passed through.
+pub fn for_each_function<R, F>(reader: R, visit: F) -> Result<()>
+    where R: Read, F: FnMut(Function) -> Result<()> {
+
+    stream(reader, None, visit).map(|_| ())
+}
+
+/// Like [`for_each_function`], but replacing whatever cannot be interpreted
+/// with placeholders as [`lenient::from_str`] does. A file that ends early
+/// is closed up, so that the function it ends in is read too.
+pub fn for_each_function_lenient<R, F>(reader: R, visit: F) -> Result<Report>
+    where R: Read, F: FnMut(Function) -> Result<()> {
+
+    stream(reader, Some(Report::default()), visit).map(|report| report.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names and number of passes of the functions handed over by a lenient
+    /// load, and whether it saw the file was truncated
+    fn lenient(contents: &str) -> (Vec<(String, usize)>, bool) {
+        let mut seen = Vec::new();
+
+        let report = for_each_function_lenient(contents.as_bytes(), |func| {
+            seen.push((func.name, func.passes.len()));
+            Ok(())
+        }).unwrap();
+
+        (seen, report.truncated)
+    }
+
+    fn seen(functions: &[(&str, usize)]) -> Vec<(String, usize)> {
+        functions.iter().map(|(name, passes)| (name.to_string(), *passes)).collect()
+    }
+
+    const PASS: &str = r#"{"name": "p", "mir": {"blocks": []}}"#;
+
+    #[test]
+    fn cut_in_the_first_function() {
+        // The pass cut short is a placeholder, as it has no `mir`
+        let contents = format!(r#"{{"functions": [{{"name": "a", "passes": [{}, {{"name": "q", "mi"#, PASS);
+        assert_eq!(lenient(&contents), (seen(&[("a", 2)]), true));
+    }
+
+    #[test]
+    fn cut_in_a_later_function() {
+        let contents = format!(r#"{{"functions": [{{"name": "a", "passes": [{}]}}, {{"name": "b", "passes": [{}, {}, {{"na"#,
+                               PASS, PASS, PASS);
+        assert_eq!(lenient(&contents), (seen(&[("a", 1), ("b", 2)]), true));
+    }
+
+    #[test]
+    fn cut_right_after_a_comma() {
+        let contents = format!(r#"{{"functions": [{{"name": "a", "passes": [{}]}},"#, PASS);
+        assert_eq!(lenient(&contents), (seen(&[("a", 1)]), true));
+    }
+
+    #[test]
+    fn cut_after_the_functions() {
+        let contents = format!(r#"{{"functions": [{{"name": "a", "passes": [{}]}}]"#, PASS);
+        assert_eq!(lenient(&contents), (seen(&[("a", 1)]), true));
+    }
+
+    #[test]
+    fn complete_file() {
+        let contents = format!(r#"{{"functions": [{{"name": "a", "passes": [{}]}}]}}"#, PASS);
+        assert_eq!(lenient(&contents), (seen(&[("a", 1)]), false));
+    }
+
+    #[test]
+    fn strict_load_of_a_cut_file() {
+        let contents = format!(r#"{{"functions": [{{"name": "a", "passes": [{}]}}, {{"name": "b"#, PASS);
+
+        let mut names = Vec::new();
+        let result = for_each_function(contents.as_bytes(), |func| {
+            names.push(func.name);
+            Ok(())
+        });
+
+        assert!(matches!(result, Err(Error::Syntax(_))));
+        assert_eq!(names, ["a"]);
+    }
+}
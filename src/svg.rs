@@ -0,0 +1,248 @@
+//! SVG drawing of the control-flow graph of a pass, laid out by [`layout`]
+//! so that Graphviz is not needed.
+//!
+//! Every function becomes one SVG document with its passes stacked on top of
+//! each other, so pick a single function with `--function` to get a file
+//! that opens as is.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::layout::{self, Edge, Layout};
+use crate::{Function, IonLog, Ir, Pass};
+
+const FONT_SIZE: f64   = 12.0;
+const CHAR_WIDTH: f64  = 7.3;
+const LINE_HEIGHT: f64 = 15.0;
+const PADDING: f64     = 6.0;
+
+/// Height of the pass titles
+const TITLE_HEIGHT: f64 = 30.0;
+
+/// Escape text for use in SVG content and attributes
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A block to draw: its number, whether it is a loop header, and its lines
+struct Node {
+    number: u32,
+    loopheader: bool,
+    lines: Vec<String>,
+}
+
+/// A pass, ready to be drawn
+struct Drawing {
+    title: String,
+    nodes: Vec<Node>,
+
+    /// `T` and `F` for the two successors of a branch
+    labels: Vec<Option<&'static str>>,
+
+    layout: Layout,
+}
+
+impl Node {
+    fn size(&self) -> (f64, f64) {
+        let chars = self.lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        (chars as f64 * CHAR_WIDTH + 2.0 * PADDING,
+         self.lines.len() as f64 * LINE_HEIGHT + 2.0 * PADDING)
+    }
+}
+
+/// Collect the blocks and edges of a pass, and lay them out. LIR blocks
+/// have no successors of their own, so the edges always come from the MIR
+/// graph.
+fn draw(title: String, pass: &Pass, ir: Ir) -> Drawing {
+
+    let mut nodes = Vec::new();
+
+    for block in pass.mir.blocks.iter().filter(|b| b.malformed.is_none()) {
+        let mut header = format!("Block#{}", block.number);
+        if !block.attributes.is_empty() {
+            header += &format!(" [{}]", block.attributes.join(", "));
+        }
+
+        let mut lines = vec![header];
+
+        let lir = pass.lir.as_ref()
+                          .filter(|_| ir == Ir::Lir)
+                          .and_then(|lir| lir.blocks.iter().find(|b| b.number == block.number &&
+                                                                     b.malformed.is_none()));
+        match lir {
+            Some(lir) => {
+                lines.extend(lir.instructions.iter().map(|instr| match instr.malformed {
+                    Some(_) => "???: <malformed>".to_string(),
+                    None => format!("{}: {}", instr.id, instr.opcode),
+                }));
+            }
+            None => {
+                lines.extend(block.instructions.iter().map(|instr| match instr.malformed {
+                    Some(_) => "???: <malformed>".to_string(),
+                    None => format!("{}: {} : {}", instr.id, instr.opcode, instr.ty),
+                }));
+            }
+        }
+
+        nodes.push(Node { number: block.number, loopheader: block.has_attribute("loopheader"), lines });
+    }
+
+    let index = nodes.iter()
+                     .enumerate()
+                     .map(|(idx, node)| (node.number, idx))
+                     .collect::<HashMap<_, _>>();
+
+    let mut edges  = Vec::new();
+    let mut labels = Vec::new();
+
+    for block in pass.mir.blocks.iter().filter(|b| b.malformed.is_none()) {
+        let branch = block.successors.len() == 2;
+
+        for (idx, succ) in block.successors.iter().enumerate() {
+            let to = match index.get(succ) {
+                Some(&to) => to,
+                None => continue,
+            };
+
+            // The jump back to the loop header is already marked as such
+            let back = block.has_attribute("backedge") && nodes[to].loopheader;
+
+            edges.push(Edge { from: index[&block.number], to, back });
+            labels.push(if branch { Some(if idx == 0 { "T" } else { "F" }) } else { None });
+        }
+    }
+
+    let sizes = nodes.iter().map(Node::size).collect::<Vec<_>>();
+    let layout = layout::layout(&sizes, &edges);
+
+    Drawing { title, nodes, labels, layout }
+}
+
+/// Write the `<g>` of one pass, `top` pixels down
+fn parse_drawing(debugout: &mut impl Write, drawing: &Drawing, top: f64) -> io::Result<()> {
+
+    let layout = &drawing.layout;
+
+    writeln!(debugout, "<g transform=\"translate(0,{})\">", top)?;
+    writeln!(debugout, "<text x=\"20\" y=\"20\" class=\"title\">{}</text>", escape(&drawing.title))?;
+    writeln!(debugout, "<g transform=\"translate(0,{})\">", TITLE_HEIGHT)?;
+
+    for (idx, route) in layout.routes.iter().enumerate() {
+        let points = route.iter()
+                          .map(|p| format!("{:.1},{:.1}", p.x, p.y))
+                          .collect::<Vec<_>>();
+        let class = if layout.back[idx] { "edge back" } else { "edge" };
+        writeln!(debugout, "<polyline class=\"{}\" points=\"{}\"/>", class, points.join(" "))?;
+
+        if let (Some(label), Some(start)) = (drawing.labels[idx], route.first()) {
+            writeln!(debugout, "<text class=\"label\" x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                     start.x + 3.0, start.y + 12.0, label)?;
+        }
+    }
+
+    for (node, pos) in drawing.nodes.iter().zip(layout.nodes.iter()) {
+        let (width, height) = node.size();
+        let class = if node.loopheader { "block loopheader" } else { "block" };
+
+        writeln!(debugout, "<g>")?;
+        writeln!(debugout, "<rect class=\"{}\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\"/>",
+                 class, pos.x, pos.y, width, height)?;
+
+        for (line, text) in node.lines.iter().enumerate() {
+            writeln!(debugout, "<text x=\"{:.1}\" y=\"{:.1}\"{}>{}</text>",
+                     pos.x + PADDING, pos.y + PADDING + (line + 1) as f64 * LINE_HEIGHT - 3.0,
+                     if line == 0 { " class=\"header\"" } else { "" }, escape(text))?;
+        }
+
+        writeln!(debugout, "</g>")?;
+    }
+
+    writeln!(debugout, "</g>\n</g>")
+}
+
+/// Write a whole SVG document holding the given drawings one under the other
+fn parse_drawings(debugout: &mut impl Write, drawings: &[Drawing]) -> io::Result<()> {
+
+    let width  = drawings.iter().map(|d| d.layout.width).fold(200.0, f64::max);
+    let height = drawings.iter().map(|d| d.layout.height + TITLE_HEIGHT).sum::<f64>();
+
+    writeln!(debugout, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(debugout, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" \
+                        viewBox=\"0 0 {:.0} {:.0}\">", width, height, width, height)?;
+    writeln!(debugout, "<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" \
+                        markerWidth=\"8\" markerHeight=\"8\" orient=\"auto\">\
+                        <path d=\"M0,0 L10,5 L0,10 z\"/></marker></defs>")?;
+    writeln!(debugout, "<style>\n\
+                        text {{ font-family: monospace; font-size: {}px; white-space: pre; }}\n\
+                        .title {{ font-size: 16px; font-weight: bold; }}\n\
+                        .header {{ font-weight: bold; }}\n\
+                        .label {{ fill: #555; }}\n\
+                        .block {{ fill: #fff; stroke: #000; }}\n\
+                        .loopheader {{ fill: #fff6d5; }}\n\
+                        .edge {{ fill: none; stroke: #000; marker-end: url(#arrow); }}\n\
+                        .back {{ stroke: #c00; stroke-dasharray: 4 3; }}\n\
+                        </style>", FONT_SIZE)?;
+    writeln!(debugout, "<rect width=\"100%\" height=\"100%\" fill=\"#fff\"/>")?;
+
+    let mut top = 0.0;
+    for drawing in drawings.iter() {
+        parse_drawing(debugout, drawing, top)?;
+        top += drawing.layout.height + TITLE_HEIGHT;
+    }
+
+    writeln!(debugout, "</svg>")
+}
+
+/// Draw the block graph of a single pass as its own SVG document
+pub fn parse_pass(debugout: &mut impl Write,
+                  title: &str, pass: &Pass, ir: Ir) -> io::Result<()> {
+
+    parse_drawings(debugout, &[draw(title.to_string(), pass, ir)])
+}
+
+/// Drawings of every pass of a function, same as the text dump passes
+/// without LIR have nothing to show with `--ir lir`
+fn draw_function(func: &Function, ir: Ir) -> Vec<Drawing> {
+
+    if func.malformed.is_some() {
+        return Vec::new();
+    }
+
+    func.passes.iter()
+               .filter(|p| p.malformed.is_none())
+               .filter(|p| ir != Ir::Lir || p.lir.is_some())
+               .map(|p| draw(format!("{} - {}", func.name, p.name), p, ir))
+               .collect()
+}
+
+/// Draw every pass of a function, one under the other, in one SVG document
+pub fn parse_function(debugout: &mut impl Write,
+                      func: &Function, ir: Ir) -> io::Result<()> {
+
+    let drawings = draw_function(func, ir);
+
+    if drawings.is_empty() {
+        return Ok(());
+    }
+
+    parse_drawings(debugout, &drawings)
+}
+
+/// Draw every pass of every function, one under the other, in one SVG
+/// document
+pub fn parse_graph(debugout: &mut impl Write,
+                   iondata: &IonLog, ir: Ir) -> io::Result<()> {
+
+    let drawings = iondata.functions.iter()
+                                    .flat_map(|func| draw_function(func, ir))
+                                    .collect::<Vec<_>>();
+
+    if drawings.is_empty() {
+        return Ok(());
+    }
+
+    parse_drawings(debugout, &drawings)
+}
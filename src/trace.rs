@@ -0,0 +1,135 @@
+//! Following a single MIR instruction, by its `id`, through every pass of a
+//! function.
+
+use std::io::{self, Write};
+
+use crate::{Function, IonLog, MirInstruction, Pass};
+
+/// What became of the instruction in one pass
+#[derive(Debug, Clone, Copy)]
+pub enum Step<'a> {
+    /// The instruction and the number of the block it is in
+    Found(u32, &'a MirInstruction),
+
+    /// Not there yet, it is created by a later pass
+    NotYetCreated,
+
+    /// It was there in an earlier pass
+    Removed,
+
+    /// Not found, but the pass has malformed entries it could be hiding in
+    Unknown,
+
+    /// The pass itself is malformed
+    Malformed,
+}
+
+/// Look the instruction up in a pass
+fn find(pass: &Pass, id: u32) -> Option<(u32, &MirInstruction)> {
+    pass.mir.blocks.iter()
+                   .filter(|b| b.malformed.is_none())
+                   .flat_map(|b| b.instructions.iter().map(move |i| (b.number, i)))
+                   .find(|(_, i)| i.malformed.is_none() && i.id == id)
+}
+
+/// What became of instruction `id` in every pass of `func`, in order
+pub fn trace(func: &Function, id: u32) -> Vec<Step<'_>> {
+
+    let mut seen = false;
+
+    func.passes.iter().map(|pass| {
+        if pass.malformed.is_some() {
+            return Step::Malformed;
+        }
+
+        if let Some((block, instr)) = find(pass, id) {
+            seen = true;
+            return Step::Found(block, instr);
+        }
+
+        let damaged = pass.mir.blocks.iter().any(|b| {
+            b.malformed.is_some() || b.instructions.iter().any(|i| i.malformed.is_some())
+        });
+
+        match (damaged, seen) {
+            (true, _)      => Step::Unknown,
+            (false, true)  => Step::Removed,
+            (false, false) => Step::NotYetCreated,
+        }
+    }).collect()
+}
+
+/// One line per pass with the block, opcode, operand and type of the
+/// instruction. The passes that created or removed it, or changed any of
+/// those, are marked with a `*`.
+pub fn parse_function(debugout: &mut impl Write, func: &Function, id: u32) -> io::Result<()> {
+
+    if func.malformed.is_some() {
+        return Ok(());
+    }
+
+    writeln!(debugout, "\n\nTrace of instruction {} in Function: {:?}\n", id, func.name)?;
+
+    let steps = trace(func, id);
+
+    let name_len = func.passes.iter().map(|p| p.name.len()).max().unwrap_or(0);
+
+    let mut opcode_len  = 0;
+    let mut operand_len = 0;
+    for step in steps.iter() {
+        if let Step::Found(_, instr) = step {
+            let (opcode, operand) = instr.opcode_and_operand();
+            opcode_len  = opcode_len.max(opcode.len());
+            operand_len = operand_len.max(operand.len());
+        }
+    }
+
+    let mut previous: Option<(u32, &MirInstruction)> = None;
+
+    for (idx, (pass, step)) in func.passes.iter().zip(steps.iter()).enumerate() {
+        let name = match step {
+            Step::Malformed => "<malformed pass>",
+            _ => &pass.name,
+        };
+
+        let line = match *step {
+            Step::Found(block, instr) => {
+                let (opcode, operand) = instr.opcode_and_operand();
+
+                let changed = match previous {
+                    Some((prev_block, prev)) => prev_block != block || prev.opcode != instr.opcode ||
+                                                prev.ty != instr.ty,
+                    // Just created
+                    None => idx > 0,
+                };
+                previous = Some((block, instr));
+
+                format!("{} Block#{:<3} {:<opw$} {:<orw$} {:?}",
+                        if changed { "*" } else { " " }, block, opcode, operand, instr.ty,
+                        opw = opcode_len + 3, orw = operand_len + 3)
+            }
+            Step::NotYetCreated => "  not yet created".to_string(),
+            Step::Removed => {
+                let mark = if previous.is_some() { "*" } else { " " };
+                previous = None;
+                format!("{} removed", mark)
+            }
+            Step::Unknown   => "? not found, the pass has malformed entries".to_string(),
+            Step::Malformed => "?".to_string(),
+        };
+
+        writeln!(debugout, "  {:<nw$}  {}", name, line, nw = name_len)?;
+    }
+
+    Ok(())
+}
+
+pub fn parse_graph(debugout: &mut impl Write,
+                   iondata: &IonLog, id: u32) -> io::Result<()> {
+
+    for func in iondata.functions.iter() {
+        parse_function(debugout, func, id)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,469 @@
+//! `iongraph tui`: browse the functions, passes and blocks of a log in the
+//! terminal instead of searching for `After Ion Phase` in `less`.
+//!
+//! The block view is the text dump of the current pass, as written by
+//! [`parse_blocks`], with the current block and search hit highlighted.
+
+use std::io;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use iongraph::{parse_blocks, Error, Function, IonLog, MirBlock, Options, Pass};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Functions,
+    Passes,
+    Blocks,
+}
+
+/// The text dump of a pass, split in lines
+struct Dump {
+    /// Function and pass it is the dump of
+    key: (usize, usize),
+
+    lines: Vec<String>,
+
+    /// Index of the empty line each block starts with
+    starts: Vec<usize>,
+}
+
+struct App {
+    iondata: IonLog,
+    options: Options,
+    focus: Focus,
+
+    /// The options for the current function, with the widths fitted to it
+    /// if `--align function` asks for that
+    fitted: Option<(usize, Options)>,
+
+    /// The dump of the current pass, kept until another one is shown
+    dump: Option<Dump>,
+
+    function: usize,
+    pass: usize,
+    block: usize,
+
+    /// Which successor of the current block Enter follows
+    successor: usize,
+
+    /// Where each Enter came from, for Backspace
+    history: Vec<(usize, usize)>,
+
+    /// The search being typed, after `/`
+    typing: Option<String>,
+    query: String,
+
+    /// Block and instruction of the last search hit, in the current pass
+    hit: Option<(usize, usize)>,
+
+    status: String,
+}
+
+impl App {
+
+    fn passes(&self) -> &[Pass] {
+        match self.iondata.functions.get(self.function) {
+            Some(func) => &func.passes,
+            None => &[],
+        }
+    }
+
+    fn blocks(&self) -> &[MirBlock] {
+        match self.passes().get(self.pass) {
+            Some(pass) if pass.malformed.is_none() => &pass.mir.blocks,
+            _ => &[],
+        }
+    }
+
+    /// Index of the block with this number in the current pass
+    fn find_block(&self, number: u32) -> Option<usize> {
+        self.blocks().iter().position(|b| b.malformed.is_none() && b.number == number)
+    }
+
+    fn select_function(&mut self, function: usize) {
+        self.function = function;
+        self.pass     = 0;
+        self.history.clear();
+        self.select_block(0);
+    }
+
+    fn select_block(&mut self, block: usize) {
+        self.block     = block;
+        self.successor = 0;
+        self.hit       = None;
+    }
+
+    /// Move to another pass of the same function, staying on the same block
+    /// if it still exists there
+    fn select_pass(&mut self, pass: usize) {
+        let number = self.blocks().get(self.block).map(|b| b.number);
+
+        self.pass = pass;
+        self.history.clear();
+        let block = number.and_then(|n| self.find_block(n)).unwrap_or(0);
+        self.select_block(block);
+    }
+
+    fn follow(&mut self) {
+        let target = self.blocks()
+                         .get(self.block)
+                         .and_then(|b| b.successors.get(self.successor).copied());
+
+        match target.and_then(|number| self.find_block(number)) {
+            Some(block) => {
+                self.history.push((self.block, self.successor));
+                self.select_block(block);
+            }
+            None => self.status = "No successor to follow".to_string(),
+        }
+    }
+
+    fn back(&mut self) {
+        if let Some((block, successor)) = self.history.pop() {
+            self.select_block(block);
+            self.successor = successor;
+        }
+    }
+
+    /// Find the next (or previous) instruction whose opcode contains the
+    /// query, going through the passes of the current function in order
+    fn search(&mut self, forward: bool) {
+
+        if self.query.is_empty() {
+            return;
+        }
+        let query = self.query.to_lowercase();
+
+        // Every instruction of the function, as (pass, block, instruction)
+        let mut hits = Vec::new();
+        for (p, pass) in self.passes().iter().enumerate() {
+            for (b, block) in pass.mir.blocks.iter().enumerate() {
+                for (i, instr) in block.instructions.iter().enumerate() {
+                    let (opcode, _) = instr.opcode_and_operand();
+                    if instr.malformed.is_none() && opcode.to_lowercase().contains(&query) {
+                        hits.push((p, b, i));
+                    }
+                }
+            }
+        }
+
+        if hits.is_empty() {
+            self.status = format!("No opcode matches {:?}", self.query);
+            return;
+        }
+
+        // Search from the last hit, or from the top of the current block
+        let here = match self.hit {
+            Some((b, i)) => (self.pass, b, i),
+            None if forward => (self.pass, self.block, 0),
+            None => (self.pass, self.block, usize::MAX),
+        };
+        let found = match forward {
+            true  => hits.iter().find(|&&h| h > here || (self.hit.is_none() && h == here)),
+            false => hits.iter().rev().find(|&&h| h < here),
+        };
+
+        let wrapped = found.is_none();
+        let &(p, b, i) = match found {
+            Some(found) => found,
+            None if forward => hits.first().unwrap(),
+            None => hits.last().unwrap(),
+        };
+
+        if p != self.pass {
+            self.pass = p;
+            self.history.clear();
+        }
+        self.select_block(b);
+        self.hit = Some((b, i));
+
+        let name = &self.passes()[p].name;
+        self.status = format!("{:?} in {:?}{}", self.query, name,
+                              if wrapped { ", search wrapped" } else { "" });
+    }
+
+    fn key(&mut self, code: KeyCode) -> bool {
+
+        if let Some(typing) = &mut self.typing {
+            match code {
+                KeyCode::Char(c) => typing.push(c),
+                KeyCode::Backspace => {
+                    typing.pop();
+                }
+                KeyCode::Enter => {
+                    self.query = self.typing.take().unwrap();
+                    self.hit = None;
+                    self.search(true);
+                }
+                KeyCode::Esc => self.typing = None,
+                _ => {}
+            }
+            return true;
+        }
+
+        self.status.clear();
+
+        let functions = self.iondata.functions.len();
+        let passes    = self.passes().len();
+        let blocks    = self.blocks().len();
+
+        match (code, self.focus) {
+            (KeyCode::Char('q'), _) | (KeyCode::Esc, _) => return false,
+
+            (KeyCode::Tab, _) => {
+                self.focus = match self.focus {
+                    Focus::Functions => Focus::Passes,
+                    Focus::Passes    => Focus::Blocks,
+                    Focus::Blocks    => Focus::Functions,
+                };
+            }
+            (KeyCode::BackTab, _) => {
+                self.focus = match self.focus {
+                    Focus::Functions => Focus::Blocks,
+                    Focus::Passes    => Focus::Functions,
+                    Focus::Blocks    => Focus::Passes,
+                };
+            }
+
+            // Step between the passes of the function from anywhere
+            (KeyCode::Left, _) | (KeyCode::Char('h'), _) if self.pass > 0 => self.select_pass(self.pass - 1),
+            (KeyCode::Right, _) | (KeyCode::Char('l'), _) if self.pass + 1 < passes => self.select_pass(self.pass + 1),
+
+            (KeyCode::Up, focus) | (KeyCode::Char('k'), focus) => match focus {
+                Focus::Functions if self.function > 0 => self.select_function(self.function - 1),
+                Focus::Passes if self.pass > 0 => self.select_pass(self.pass - 1),
+                Focus::Blocks if self.block > 0 => self.select_block(self.block - 1),
+                _ => {}
+            },
+            (KeyCode::Down, focus) | (KeyCode::Char('j'), focus) => match focus {
+                Focus::Functions if self.function + 1 < functions => self.select_function(self.function + 1),
+                Focus::Passes if self.pass + 1 < passes => self.select_pass(self.pass + 1),
+                Focus::Blocks if self.block + 1 < blocks => self.select_block(self.block + 1),
+                _ => {}
+            },
+
+            (KeyCode::Enter, Focus::Functions) => self.focus = Focus::Passes,
+            (KeyCode::Enter, Focus::Passes)    => self.focus = Focus::Blocks,
+            (KeyCode::Enter, Focus::Blocks)    => self.follow(),
+            (KeyCode::Backspace, _) => self.back(),
+
+            (KeyCode::Char('s'), _) => {
+                let count = self.blocks().get(self.block).map_or(0, |b| b.successors.len());
+                if count > 0 {
+                    self.successor = (self.successor + 1) % count;
+                }
+            }
+
+            (KeyCode::Char('/'), _) => self.typing = Some(String::new()),
+            (KeyCode::Char('n'), _) => self.search(true),
+            (KeyCode::Char('N'), _) => self.search(false),
+
+            _ => {}
+        }
+
+        true
+    }
+
+    /// The options to dump the current pass with, the same as the text dump
+    fn pass_options(&mut self) -> Options {
+
+        let func = match self.iondata.functions.get(self.function) {
+            Some(func) => func,
+            None => return self.options,
+        };
+
+        let options = match self.fitted {
+            Some((function, options)) if function == self.function => options,
+            _ => {
+                let options = self.options.for_function(func);
+                self.fitted = Some((self.function, options));
+                options
+            }
+        };
+
+        match self.passes().get(self.pass) {
+            Some(pass) => options.for_pass(pass),
+            None => options,
+        }
+    }
+
+    /// The dump of the current pass, only rendered again when another pass or
+    /// function is shown
+    fn dump(&mut self) -> &Dump {
+
+        let key = (self.function, self.pass);
+
+        if self.dump.as_ref().map(|d| d.key) != Some(key) {
+            let options = self.pass_options();
+
+            let mut dump = Vec::new();
+            parse_blocks(&mut dump, self.blocks(), &options)
+                .expect("writing to a Vec cannot fail");
+
+            let lines = String::from_utf8_lossy(&dump)
+                .lines()
+                .map(String::from)
+                .collect::<Vec<_>>();
+
+            // Each block starts with an empty line
+            let starts = lines.iter()
+                              .enumerate()
+                              .filter(|(_, line)| line.is_empty())
+                              .map(|(idx, _)| idx)
+                              .collect();
+
+            self.dump = Some(Dump { key, lines, starts });
+        }
+
+        self.dump.as_ref().unwrap()
+    }
+
+    fn draw_block_view(&mut self, frame: &mut Frame, area: Rect) {
+
+        let title = match self.passes().get(self.pass) {
+            Some(pass) if pass.malformed.is_some() => " <malformed pass> ".to_string(),
+            Some(pass) => format!(" After Ion Phase {:?} ", pass.name),
+            None => String::new(),
+        };
+        let focused = self.focus == Focus::Blocks;
+        let (current, hit) = (self.block, self.hit);
+
+        let Dump { lines, starts, .. } = self.dump();
+
+        let current = starts.get(current).map(|&start| {
+            let end = starts.get(current + 1).copied().unwrap_or(lines.len());
+            start..end
+        }).unwrap_or(0..0);
+        let hit = hit.and_then(|(b, i)| starts.get(b).map(|start| start + 2 + i));
+
+        // Keep the current block, and the hit in it, on screen. Only the
+        // lines that fit are styled and handed over, so there is no scroll
+        // offset to overflow on long passes.
+        let height = area.height.saturating_sub(2) as usize;
+        let mut scroll = current.start;
+        if let Some(hit) = hit {
+            scroll = scroll.max((hit + 2).saturating_sub(height));
+        }
+        let visible = scroll.min(lines.len())..(scroll + height).min(lines.len());
+
+        let text = lines[visible.clone()].iter().zip(visible).map(|(line, idx)| {
+            let style = if Some(idx) == hit {
+                Style::default().bg(Color::Yellow).fg(Color::Black)
+            } else if idx == current.start + 1 {
+                Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+            } else if current.contains(&idx) {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            Line::styled(line.clone(), style)
+        }).collect::<Vec<_>>();
+
+        let block = Block::bordered().title(title).border_style(border(focused));
+        frame.render_widget(Paragraph::new(text).block(block), area);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+
+        let [main, status] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)])
+                                    .areas(frame.area());
+        let [lists, view] = Layout::horizontal([Constraint::Length(40), Constraint::Min(0)])
+                                   .areas(main);
+        let [functions, passes] = Layout::vertical([Constraint::Percentage(50),
+                                                    Constraint::Percentage(50)])
+                                         .areas(lists);
+
+        let names = self.iondata.functions.iter().enumerate().map(|(idx, func): (usize, &Function)| {
+            match func.malformed {
+                Some(_) => format!("{}: <malformed function>", idx),
+                None => format!("{}: {}", idx, func.name),
+            }
+        });
+        let list = List::new(names)
+            .block(Block::bordered().title(" Functions ").border_style(border(self.focus == Focus::Functions)))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, functions,
+                                     &mut ListState::default().with_selected(Some(self.function)));
+
+        let names = self.passes().iter().enumerate().map(|(idx, pass)| {
+            match pass.malformed {
+                Some(_) => format!("{}: <malformed pass>", idx),
+                None => format!("{}: {}", idx, pass.name),
+            }
+        });
+        let list = List::new(names)
+            .block(Block::bordered().title(" Passes ").border_style(border(self.focus == Focus::Passes)))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, passes,
+                                     &mut ListState::default().with_selected(Some(self.pass)));
+
+        self.draw_block_view(frame, view);
+
+        let line = match &self.typing {
+            Some(typing) => format!("/{}", typing),
+            None if !self.status.is_empty() => self.status.clone(),
+            None => {
+                let next = self.blocks()
+                               .get(self.block)
+                               .and_then(|b| b.successors.get(self.successor))
+                               .map(|s| format!("Enter: Block#{}  ", s))
+                               .unwrap_or_default();
+                format!("{}Tab: pane  \u{2190}\u{2192}: pass  s: successor  Backspace: back  \
+                         /: search  n/N: next/prev  q: quit", next)
+            }
+        };
+        frame.render_widget(Paragraph::new(line), status);
+    }
+}
+
+fn border(focused: bool) -> Style {
+    match focused {
+        true  => Style::default().fg(Color::Cyan),
+        false => Style::default(),
+    }
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Browse `iondata` until `q` is pressed
+pub fn run(iondata: IonLog, options: Options) -> iongraph::Result<()> {
+
+    let mut app = App {
+        iondata,
+        options,
+        focus: Focus::Functions,
+        fitted: None,
+        dump: None,
+        function: 0,
+        pass: 0,
+        block: 0,
+        successor: 0,
+        history: Vec::new(),
+        typing: None,
+        query: String::new(),
+        hit: None,
+        status: String::new(),
+    };
+
+    let mut terminal = ratatui::try_init().map_err(Error::Terminal)?;
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+
+    result.map_err(Error::Terminal)
+}